@@ -0,0 +1,56 @@
+//! Generates the protocol table in `src/protocols.rs` from the vendored copy
+//! of the multiformats `protocols.csv`.
+
+use std::env;
+use std::fs::File;
+use std::io::{ Read, Write };
+use std::path::Path;
+
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let csv_path = Path::new(&manifest_dir).join("protocols.csv");
+    println!("cargo:rerun-if-changed={}", csv_path.display());
+
+    let mut csv = String::new();
+    File::open(&csv_path).unwrap().read_to_string(&mut csv).unwrap();
+
+    let mut output = String::new();
+    let mut names = Vec::new();
+    // Skip the header line
+    for line in csv.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+        let fields: Vec<&str> = line.splitn(4, ',').map(|field| field.trim()).collect();
+        assert!(fields.len() >= 3, "invalid line in protocols.csv: {}", line);
+        let code: u64 = fields[0].parse().unwrap();
+        let size = match fields[1] {
+            "V" | "-1" => "Size::LengthPrefixed".to_owned(),
+            bits => {
+                let bits: usize = bits.parse().unwrap();
+                assert!(bits % 8 == 0, "size of {} isn't a whole number of bytes", fields[2]);
+                format!("Size::Fixed({})", bits / 8)
+            }
+        };
+        let name = fields[2];
+        let comment = fields.get(3).map_or("", |comment| comment.trim());
+        let ident = name.to_uppercase().replace('-', "_");
+
+        output.push_str(&format!("/// The `{}` protocol", name));
+        if !comment.is_empty() {
+            output.push_str(&format!(", {}", comment));
+        }
+        output.push_str(".\n");
+        output.push_str(&format!(
+            "pub const {}: ProtocolInfo = ProtocolInfo {{ code: {}, name: {:?}, size: {} }};\n\n",
+            ident, code, name, size));
+        names.push(ident);
+    }
+
+    output.push_str("/// All protocols in the table, in the order they are listed.\n");
+    output.push_str("pub const ALL: &'static [ProtocolInfo] = &[\n");
+    for name in names {
+        output.push_str(&format!("    {},\n", name));
+    }
+    output.push_str("];\n");
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("protocols.rs");
+    File::create(out_path).unwrap().write_all(output.as_bytes()).unwrap();
+}
@@ -0,0 +1,334 @@
+//! Asynchronous equivalents of `ReadMultiAddr` and `WriteMultiAddr`.
+//!
+//! The traits are available for the `AsyncRead`/`AsyncWrite` traits of
+//! [tokio][] in the `tokio` module when the `tokio` feature is enabled, and
+//! of [futures-io][] in the `futures` module when the `futures-io` feature is
+//! enabled.
+//!
+//! [tokio]: https://docs.rs/tokio
+//! [futures-io]: https://docs.rs/futures-io
+
+use std::io;
+use std::mem;
+use std::task::{ Context, Poll };
+
+use { MultiAddr, ReadMultiAddr, WriteMultiAddr };
+use decoder::read_varint;
+
+macro_rules! try_ready {
+    ($e:expr) => (match $e {
+        Poll::Ready(Ok(value)) => value,
+        Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+        Poll::Pending => return Poll::Pending,
+    })
+}
+
+/// Define `AsyncReadMultiAddr` and `AsyncWriteMultiAddr` and their futures
+/// for the `AsyncRead` and `AsyncWrite` traits in scope, the invoking module
+/// must implement `PollRead` for `Adapter` as reading differs between them.
+macro_rules! async_multiaddr_traits {
+    (
+        $(#[$read_attr:meta])* read;
+        $(#[$write_attr:meta])* write;
+    ) => {
+        $(#[$read_attr])*
+        pub trait AsyncReadMultiAddr {
+            /// Read a `MultiAddr` from this object, consuming the entire
+            /// object, see `ReadMultiAddr::read_multiaddr`.
+            ///
+            /// Everything up to EOF is buffered without any limit, for
+            /// untrusted input bound the object first, e.g. with `take`, or
+            /// use `read_multiaddr_length_prefixed`.
+            fn read_multiaddr<'a>(&'a mut self) -> ReadMultiAddrFuture<'a, Self>;
+
+            /// Read a `MultiAddr` prefixed with its encoded length as a
+            /// varint, see `ReadMultiAddr::read_multiaddr_length_prefixed`.
+            fn read_multiaddr_length_prefixed<'a>(&'a mut self, max_length: usize) -> ReadMultiAddrFuture<'a, Self>;
+        }
+
+        $(#[$write_attr])*
+        pub trait AsyncWriteMultiAddr {
+            /// Write a `MultiAddr` to this object, see
+            /// `WriteMultiAddr::write_multiaddr`.
+            fn write_multiaddr<'a>(&'a mut self, multiaddr: &MultiAddr) -> WriteMultiAddrFuture<'a, Self>;
+
+            /// Write a `MultiAddr` prefixed with its encoded length as a
+            /// varint, see `WriteMultiAddr::write_multiaddr_length_prefixed`.
+            fn write_multiaddr_length_prefixed<'a>(&'a mut self, multiaddr: &MultiAddr) -> WriteMultiAddrFuture<'a, Self>;
+        }
+
+        /// The future returned by the methods of `AsyncReadMultiAddr`.
+        #[derive(Debug)]
+        pub struct ReadMultiAddrFuture<'a, R: ?Sized + 'a> {
+            reader: &'a mut R,
+            frame: ReadFrame,
+        }
+
+        /// The future returned by the methods of `AsyncWriteMultiAddr`.
+        #[derive(Debug)]
+        pub struct WriteMultiAddrFuture<'a, W: ?Sized + 'a> {
+            writer: &'a mut W,
+            frame: WriteFrame,
+        }
+
+        struct Adapter<'a, T: ?Sized + 'a>(&'a mut T);
+
+        impl<'a, W: AsyncWrite + Unpin + ?Sized> PollWrite for Adapter<'a, W> {
+            fn poll_write(&mut self, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+                Pin::new(&mut *self.0).poll_write(cx, buf)
+            }
+        }
+
+        impl<R: AsyncRead + Unpin + ?Sized> AsyncReadMultiAddr for R {
+            fn read_multiaddr<'a>(&'a mut self) -> ReadMultiAddrFuture<'a, Self> {
+                ReadMultiAddrFuture { reader: self, frame: ReadFrame::to_eof() }
+            }
+
+            fn read_multiaddr_length_prefixed<'a>(&'a mut self, max_length: usize) -> ReadMultiAddrFuture<'a, Self> {
+                ReadMultiAddrFuture { reader: self, frame: ReadFrame::length_prefixed(max_length) }
+            }
+        }
+
+        impl<W: AsyncWrite + Unpin + ?Sized> AsyncWriteMultiAddr for W {
+            fn write_multiaddr<'a>(&'a mut self, multiaddr: &MultiAddr) -> WriteMultiAddrFuture<'a, Self> {
+                WriteMultiAddrFuture { writer: self, frame: WriteFrame::new(multiaddr, false) }
+            }
+
+            fn write_multiaddr_length_prefixed<'a>(&'a mut self, multiaddr: &MultiAddr) -> WriteMultiAddrFuture<'a, Self> {
+                WriteMultiAddrFuture { writer: self, frame: WriteFrame::new(multiaddr, true) }
+            }
+        }
+
+        impl<'a, R: AsyncRead + Unpin + ?Sized> Future for ReadMultiAddrFuture<'a, R> {
+            type Output = io::Result<MultiAddr>;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<MultiAddr>> {
+                let this = &mut *self;
+                this.frame.poll(&mut Adapter(&mut *this.reader), cx)
+            }
+        }
+
+        impl<'a, W: AsyncWrite + Unpin + ?Sized> Future for WriteMultiAddrFuture<'a, W> {
+            type Output = io::Result<()>;
+
+            fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+                let this = &mut *self;
+                this.frame.poll(&mut Adapter(&mut *this.writer), cx)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "futures-io")]
+pub mod futures;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+/// A source of bytes that can be polled, implemented by adapters for each
+/// supported `AsyncRead` trait.
+pub(crate) trait PollRead {
+    fn poll_read(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+}
+
+/// A sink of bytes that can be polled, implemented by adapters for each
+/// supported `AsyncWrite` trait.
+pub(crate) trait PollWrite {
+    fn poll_write(&mut self, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>>;
+}
+
+#[derive(Debug)]
+enum ReadState {
+    /// Reading until EOF into the buffer.
+    ToEof,
+    /// Reading the varint length prefix into the buffer a byte at a time.
+    Length { max_length: usize },
+    /// Reading the encoded multiaddr into the buffer, `filled` bytes have
+    /// already been read.
+    Data { filled: usize },
+    /// The multiaddr has been returned.
+    Done,
+}
+
+/// The state of reading a single `MultiAddr` from a `PollRead`.
+#[derive(Debug)]
+pub(crate) struct ReadFrame {
+    state: ReadState,
+    buffer: Vec<u8>,
+}
+
+/// The state of writing a single encoded `MultiAddr` to a `PollWrite`.
+#[derive(Debug)]
+pub(crate) struct WriteFrame {
+    buffer: Vec<u8>,
+    written: usize,
+}
+
+impl ReadFrame {
+    /// Read until EOF, the caller must bound the reader as the buffer grows
+    /// without limit.
+    pub(crate) fn to_eof() -> ReadFrame {
+        ReadFrame { state: ReadState::ToEof, buffer: Vec::new() }
+    }
+
+    pub(crate) fn length_prefixed(max_length: usize) -> ReadFrame {
+        ReadFrame {
+            state: ReadState::Length { max_length: max_length },
+            buffer: Vec::new(),
+        }
+    }
+
+    pub(crate) fn poll<R: PollRead>(&mut self, reader: &mut R, cx: &mut Context) -> Poll<io::Result<MultiAddr>> {
+        loop {
+            match self.state {
+                ReadState::ToEof => {
+                    let mut chunk = [0; 256];
+                    match try_ready!(reader.poll_read(cx, &mut chunk)) {
+                        0 => return Poll::Ready(self.finish()),
+                        read => self.buffer.extend_from_slice(&chunk[..read]),
+                    }
+                }
+                ReadState::Length { max_length } => {
+                    let mut byte = [0];
+                    if try_ready!(reader.poll_read(cx, &mut byte)) == 0 {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "Truncated length prefix")));
+                    }
+                    self.buffer.push(byte[0]);
+                    match read_varint(&self.buffer) {
+                        Ok(Some((length, _))) => {
+                            if length > max_length as u64 {
+                                return Poll::Ready(Err(io::Error::new(
+                                    io::ErrorKind::InvalidData,
+                                    format!("Multiaddr length {} exceeds maximum {}", length, max_length))));
+                            }
+                            self.buffer = vec![0; length as usize];
+                            self.state = ReadState::Data { filled: 0 };
+                        }
+                        Ok(None) => (),
+                        Err(err) => return Poll::Ready(Err(err)),
+                    }
+                }
+                ReadState::Data { filled } if filled == self.buffer.len() => {
+                    return Poll::Ready(self.finish());
+                }
+                ReadState::Data { filled } => {
+                    match try_ready!(reader.poll_read(cx, &mut self.buffer[filled..])) {
+                        0 => {
+                            return Poll::Ready(Err(io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "Truncated multiaddr")));
+                        }
+                        read => self.state = ReadState::Data { filled: filled + read },
+                    }
+                }
+                ReadState::Done => panic!("multiaddr read polled after completion"),
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<MultiAddr> {
+        self.state = ReadState::Done;
+        let buffer = mem::take(&mut self.buffer);
+        (&buffer[..]).read_multiaddr()
+    }
+}
+
+impl WriteFrame {
+    pub(crate) fn new(multiaddr: &MultiAddr, length_prefixed: bool) -> WriteFrame {
+        let mut buffer = Vec::new();
+        if length_prefixed {
+            buffer.write_multiaddr_length_prefixed(multiaddr)
+        } else {
+            buffer.write_multiaddr(multiaddr)
+        }.expect("writing to a Vec cannot fail");
+        WriteFrame { buffer: buffer, written: 0 }
+    }
+
+    pub(crate) fn poll<W: PollWrite>(&mut self, writer: &mut W, cx: &mut Context) -> Poll<io::Result<()>> {
+        while self.written < self.buffer.len() {
+            match try_ready!(writer.poll_write(cx, &self.buffer[self.written..])) {
+                0 => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "Failed to write whole multiaddr")));
+                }
+                written => self.written += written,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::task::{ Context, Poll };
+
+    use futures::task::noop_waker_ref;
+
+    use { MultiAddr, Segment };
+    use super::{ PollRead, PollWrite, ReadFrame, WriteFrame };
+
+    /// Alternates between returning `Pending` and transferring one byte.
+    struct Trickle {
+        bytes: Vec<u8>,
+        ready: bool,
+    }
+
+    impl PollRead for Trickle {
+        fn poll_read(&mut self, _: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                return Poll::Pending;
+            }
+            if self.bytes.is_empty() || buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            buf[0] = self.bytes.remove(0);
+            Poll::Ready(Ok(1))
+        }
+    }
+
+    impl PollWrite for Trickle {
+        fn poll_write(&mut self, _: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                return Poll::Pending;
+            }
+            self.bytes.push(buf[0]);
+            Poll::Ready(Ok(1))
+        }
+    }
+
+    fn poll_read(frame: &mut ReadFrame, trickle: &mut Trickle) -> io::Result<MultiAddr> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        loop {
+            if let Poll::Ready(result) = frame.poll(trickle, &mut cx) {
+                return result;
+            }
+        }
+    }
+
+    #[test]
+    fn trickle() {
+        let addr = MultiAddr::new(vec![Segment::Dns4("example.com".to_owned()), Segment::Tcp(22)]);
+        let mut trickle = Trickle { bytes: Vec::new(), ready: false };
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        for &length_prefixed in &[true, false] {
+            let mut frame = WriteFrame::new(&addr, length_prefixed);
+            while frame.poll(&mut trickle, &mut cx).is_pending() {}
+        }
+
+        assert_eq!(addr, poll_read(&mut ReadFrame::length_prefixed(16), &mut trickle).unwrap());
+        assert_eq!(addr, poll_read(&mut ReadFrame::to_eof(), &mut trickle).unwrap());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut trickle = Trickle { bytes: vec![5, 4, 1, 2], ready: false };
+        let err = poll_read(&mut ReadFrame::length_prefixed(16), &mut trickle).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+}
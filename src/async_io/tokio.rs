@@ -0,0 +1,54 @@
+//! `AsyncReadMultiAddr` and `AsyncWriteMultiAddr` for tokio's `AsyncRead` and
+//! `AsyncWrite` traits.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+use ::tokio::io::{ AsyncRead, AsyncWrite, ReadBuf };
+
+use MultiAddr;
+use super::{ PollRead, PollWrite, ReadFrame, WriteFrame };
+
+async_multiaddr_traits! {
+    /// A trait to allow asynchronously reading a `MultiAddr` from a tokio
+    /// `AsyncRead`, see `ReadMultiAddr`.
+    read;
+    /// A trait to allow asynchronously writing a `MultiAddr` to a tokio
+    /// `AsyncWrite`, see `WriteMultiAddr`.
+    write;
+}
+
+impl<'a, R: AsyncRead + Unpin + ?Sized> PollRead for Adapter<'a, R> {
+    fn poll_read(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        Pin::new(&mut *self.0).poll_read(cx, &mut buf).map_ok(|()| buf.filled().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use { MultiAddr, Segment };
+    use super::{ AsyncReadMultiAddr, AsyncWriteMultiAddr };
+
+    #[test]
+    fn round_trip() {
+        let addr = MultiAddr::new(vec![Segment::Dns4("example.com".to_owned()), Segment::Tcp(22)]);
+        let mut buffer = Vec::new();
+        block_on(buffer.write_multiaddr_length_prefixed(&addr)).unwrap();
+        block_on(buffer.write_multiaddr(&addr)).unwrap();
+
+        let mut reader = &buffer[..];
+        assert_eq!(addr, block_on(reader.read_multiaddr_length_prefixed(32)).unwrap());
+        assert_eq!(addr, block_on(reader.read_multiaddr()).unwrap());
+    }
+
+    #[test]
+    fn too_long() {
+        let mut reader: &[u8] = &[5, 4, 1, 2, 3, 4];
+        assert!(block_on(reader.read_multiaddr_length_prefixed(4)).is_err());
+    }
+}
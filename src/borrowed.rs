@@ -0,0 +1,453 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{ Hash, Hasher };
+use std::io;
+use std::net::{ Ipv4Addr, Ipv6Addr };
+use std::str;
+
+use data_encoding::{ BASE32_NOPAD, BASE64, Encoding };
+
+use { MultiAddr, Registry, Segment, Size };
+use decoder::{ Frame, frame, protocol_info };
+use display::write_escaped;
+use protocol::EMPTY;
+use protocols::ProtocolInfo;
+use read::read_segment_value;
+
+/// A multiaddr borrowed from a binary encoded buffer.
+///
+/// The buffer is validated once on construction, after that iterating over
+/// segments and inspecting their codes and data works directly on the
+/// borrowed bytes without allocating.
+///
+/// # Examples
+///
+/// ```rust
+/// use maddr::{ MultiAddrRef, Segment };
+///
+/// let bytes = [4, 1, 2, 3, 4, 6, 0, 22];
+/// let addr = MultiAddrRef::new(&bytes).unwrap();
+///
+/// assert!(addr.segments().any(|segment| segment.name() == "tcp"));
+/// assert_eq!("/ip4/1.2.3.4/tcp/22", addr.to_string());
+/// assert_eq!(&Segment::Tcp(22), &addr.to_owned().segments()[1]);
+/// ```
+///
+/// Equality, hashing and ordering only consider the encoded bytes, not the
+/// registry used to decode them.
+#[derive(Copy, Clone)]
+pub struct MultiAddrRef<'a> {
+    bytes: &'a [u8],
+    registry: &'a Registry,
+}
+
+/// A single segment of a `MultiAddrRef`.
+#[derive(Copy, Clone)]
+pub struct SegmentRef<'a> {
+    code: u64,
+    value: &'a [u8],
+    data: &'a [u8],
+    registry: &'a Registry,
+}
+
+/// An iterator over the segments of a `MultiAddrRef`.
+#[derive(Debug, Clone)]
+pub struct SegmentRefs<'a> {
+    bytes: &'a [u8],
+    registry: &'a Registry,
+}
+
+/// Split the first segment off the given bytes without decoding its value.
+fn split_segment<'a>(bytes: &'a [u8], registry: &'a Registry) -> io::Result<(SegmentRef<'a>, &'a [u8])> {
+    let (code, value, data, end) = match try!(frame(bytes, registry)) {
+        Frame::Complete { code, value, data, end } => (code, value, data, end),
+        Frame::Incomplete(_) => {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated segment"));
+        }
+    };
+    let (segment, rest) = bytes.split_at(end);
+    let segment = SegmentRef {
+        code: code,
+        value: &segment[value..],
+        data: &segment[data..],
+        registry: registry,
+    };
+    Ok((segment, rest))
+}
+
+/// Check a segment's value as `ReadMultiAddr::read_multiaddr` would, values
+/// that would be decoded into a `String` or `Vec` are checked in place to
+/// avoid allocating, only multihashes are decoded as `mhash` has no borrowed
+/// representation.
+fn validate(segment: &SegmentRef) -> io::Result<()> {
+    let invalid = |message| Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    match segment.code {
+        42 | 53 | 54 | 55 | 56 | 400 | 449 | 481 => {
+            if let Err(err) = str::from_utf8(segment.data) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
+            if segment.code == 42 && segment.data.is_empty() {
+                return invalid("Empty ip6zone");
+            }
+            Ok(())
+        }
+        446 if segment.data.len() < 386 => invalid("Garlic address too short"),
+        446 if segment.data.len() > 462 => invalid("Garlic address too long"),
+        447 if segment.data.len() != 32 && segment.data.len() < 35 => {
+            invalid("Garlic address too short")
+        }
+        446 | 447 => Ok(()),
+        code => match (protocol_info(code), segment.registry.get(code)) {
+            (None, Some(protocol)) => protocol.validate_value(segment.data),
+            // The remaining values are decoded onto the stack
+            _ => read_segment_value(code, segment.value, segment.registry).map(|_| ()),
+        },
+    }
+}
+
+/// Write `data` in the given encoding without allocating, by encoding a
+/// whole number of blocks at a time into a buffer on the stack and passing
+/// each character through `map`.
+fn write_encoded<F>(f: &mut fmt::Formatter, encoding: &Encoding, block: usize, data: &[u8], map: F)
+    -> fmt::Result where F: Fn(u8) -> u8
+{
+    let mut buffer = [0; 64];
+    for chunk in data.chunks(block * 8) {
+        let output = &mut buffer[..encoding.encode_len(chunk.len())];
+        encoding.encode_mut(chunk, output);
+        for byte in output.iter_mut() {
+            *byte = map(*byte);
+        }
+        try!(f.write_str(str::from_utf8(output).expect("encodings are ascii")));
+    }
+    Ok(())
+}
+
+fn write_base32(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+    write_encoded(f, &BASE32_NOPAD, 5, data, |c| c.to_ascii_lowercase())
+}
+
+fn write_i2p_base64(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+    // I2P uses a base64 alphabet with `-~` in place of `+/`
+    write_encoded(f, &BASE64, 3, data, |c| match c {
+        b'+' => b'-',
+        b'/' => b'~',
+        c => c,
+    })
+}
+
+impl<'a> MultiAddrRef<'a> {
+    /// Validate the given binary encoded multiaddr and borrow it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes aren't a valid encoded multiaddr, as
+    /// `ReadMultiAddr::read_multiaddr` would.
+    pub fn new(bytes: &'a [u8]) -> io::Result<MultiAddrRef<'a>> {
+        MultiAddrRef::with_registry(bytes, &EMPTY)
+    }
+
+    /// Validate the given binary encoded multiaddr and borrow it, supporting
+    /// the protocols in the registry in addition to the built in protocols.
+    /// Segments using a registered protocol are displayed using the
+    /// protocol's name and value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the bytes aren't a valid encoded multiaddr, as
+    /// `Registry::read_multiaddr` would.
+    pub fn with_registry(bytes: &'a [u8], registry: &'a Registry) -> io::Result<MultiAddrRef<'a>> {
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let (segment, remaining) = try!(split_segment(rest, registry));
+            try!(validate(&segment));
+            rest = remaining;
+        }
+        Ok(MultiAddrRef { bytes: bytes, registry: registry })
+    }
+
+    /// Borrow bytes that are already known to be a valid encoded multiaddr
+    /// using the given registry.
+    pub(crate) fn from_validated(bytes: &'a [u8], registry: &'a Registry) -> MultiAddrRef<'a> {
+        MultiAddrRef { bytes: bytes, registry: registry }
+    }
+
+    /// Get the binary encoding of this address.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Iterate over the segments of this address.
+    pub fn segments(&self) -> SegmentRefs<'a> {
+        SegmentRefs { bytes: self.bytes, registry: self.registry }
+    }
+
+    /// Decode this address into an owned `MultiAddr`.
+    pub fn to_owned(&self) -> MultiAddr {
+        MultiAddr::new(self.segments().map(|segment| segment.to_owned()).collect())
+    }
+}
+
+impl<'a> SegmentRef<'a> {
+    /// Get the code identifying this segment's protocol, as it was encoded.
+    pub fn code(&self) -> u64 {
+        self.code
+    }
+
+    /// Get the entry in the protocol table for this segment's protocol, if
+    /// it's one of the built in protocols.
+    pub fn protocol(&self) -> Option<&'static ProtocolInfo> {
+        protocol_info(self.code)
+    }
+
+    /// Get the name of this segment's protocol, as `Segment::name`.
+    pub fn name(&self) -> &'static str {
+        self.protocol().map_or("unknown", |protocol| protocol.name)
+    }
+
+    /// Get the binary value of this segment, excluding any length prefix.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Decode this segment into an owned `Segment`.
+    pub fn to_owned(&self) -> Segment {
+        read_segment_value(self.code, self.value, self.registry)
+            .expect("segment was validated on construction")
+    }
+}
+
+impl<'a> Iterator for SegmentRefs<'a> {
+    type Item = SegmentRef<'a>;
+
+    fn next(&mut self) -> Option<SegmentRef<'a>> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let (segment, rest) = split_segment(self.bytes, self.registry)
+            .expect("multiaddr was validated on construction");
+        self.bytes = rest;
+        Some(segment)
+    }
+}
+
+impl<'a> fmt::Display for MultiAddrRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for segment in self.segments() {
+            try!(fmt::Display::fmt(&segment, f));
+        }
+        Ok(())
+    }
+}
+
+/// Displays the same as the equivalent `Segment` displayed with the registry,
+/// writing directly from the borrowed bytes except for multihashes, which are
+/// decoded first.
+impl<'a> fmt::Display for SegmentRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let data = self.data;
+        let port = |bytes: &[u8]| (u16::from(bytes[0]) << 8) | u16::from(bytes[1]);
+        if let (None, Some(protocol)) = (self.protocol(), self.registry.get(self.code)) {
+            try!(write!(f, "/{}", protocol.name()));
+            if protocol.size() != Size::Fixed(0) {
+                try!(f.write_str("/"));
+                try!(protocol.fmt_value(data, f));
+            }
+            return Ok(());
+        }
+        match self.code {
+            421 | 466 => return fmt::Display::fmt(&self.to_owned(), f),
+            _ => try!(write!(f, "/{}", self.name())),
+        }
+        match self.code {
+            4 => write!(f, "/{}", Ipv4Addr::new(data[0], data[1], data[2], data[3])),
+            41 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                write!(f, "/{}", Ipv6Addr::from(octets))
+            }
+            6 | 17 | 33 | 132 | 273 => write!(f, "/{}", port(data)),
+            43 => write!(f, "/{}", data[0]),
+            777 => {
+                let id = data.iter().fold(0u64, |id, &byte| (id << 8) | u64::from(byte));
+                write!(f, "/{}", id)
+            }
+            42 | 53 | 54 | 55 | 56 | 449 => {
+                write!(f, "/{}", str::from_utf8(data).expect("segment was validated on construction"))
+            }
+            400 => {
+                let path = str::from_utf8(data).expect("segment was validated on construction");
+                if !path.starts_with('/') {
+                    try!(f.write_str("/"));
+                }
+                write_escaped(f, path, &[])
+            }
+            481 => {
+                let path = str::from_utf8(data).expect("segment was validated on construction");
+                try!(f.write_str("/"));
+                write_escaped(f, path, &['/', '?', '#'])
+            }
+            444 | 445 => {
+                let (addr, port_bytes) = data.split_at(data.len() - 2);
+                try!(f.write_str("/"));
+                try!(write_base32(f, addr));
+                write!(f, ":{}", port(port_bytes))
+            }
+            446 => {
+                try!(f.write_str("/"));
+                write_i2p_base64(f, data)
+            }
+            447 => {
+                try!(f.write_str("/"));
+                write_base32(f, data)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a> PartialEq for MultiAddrRef<'a> {
+    fn eq(&self, other: &MultiAddrRef<'a>) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl<'a> Eq for MultiAddrRef<'a> {}
+
+impl<'a> Hash for MultiAddrRef<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bytes.hash(state)
+    }
+}
+
+impl<'a> PartialOrd for MultiAddrRef<'a> {
+    fn partial_cmp(&self, other: &MultiAddrRef<'a>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for MultiAddrRef<'a> {
+    fn cmp(&self, other: &MultiAddrRef<'a>) -> Ordering {
+        self.bytes.cmp(other.bytes)
+    }
+}
+
+impl<'a> PartialEq for SegmentRef<'a> {
+    fn eq(&self, other: &SegmentRef<'a>) -> bool {
+        self.code == other.code && self.value == other.value
+    }
+}
+
+impl<'a> Eq for SegmentRef<'a> {}
+
+impl<'a> Hash for SegmentRef<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.code.hash(state);
+        self.value.hash(state);
+    }
+}
+
+impl<'a> fmt::Debug for MultiAddrRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(f.write_str("MultiAddrRef(\""));
+        try!(fmt::Display::fmt(self, f));
+        try!(f.write_str("\")"));
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Debug for SegmentRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(f.write_str("SegmentRef(\""));
+        try!(fmt::Display::fmt(self, f));
+        try!(f.write_str("\")"));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ error, fmt, str };
+
+    use { MultiAddr, MultiAddrRef, Protocol, Registry, Segment, Size, WriteMultiAddr };
+
+    #[derive(Debug)]
+    struct Label;
+
+    impl Protocol for Label {
+        fn code(&self) -> u64 { 0x300001 }
+        fn name(&self) -> &str { "label" }
+        fn size(&self) -> Size { Size::LengthPrefixed }
+        fn parse_value(&self, value: &str) -> Result<Vec<u8>, Box<dyn error::Error + Send + Sync>> {
+            Ok(value.as_bytes().to_vec())
+        }
+        fn fmt_value(&self, value: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(try!(str::from_utf8(value).map_err(|_| fmt::Error)))
+        }
+    }
+
+    fn encode(s: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.write_multiaddr(&s.parse::<MultiAddr>().unwrap()).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn segments() {
+        let bytes = encode("/dns4/example.com/tcp/443/wss");
+        let addr = MultiAddrRef::new(&bytes).unwrap();
+        let segments: Vec<_> = addr.segments().collect();
+        assert_eq!(3, segments.len());
+        assert_eq!((54, "dns4", &b"example.com"[..]),
+            (segments[0].code(), segments[0].name(), segments[0].data()));
+        assert_eq!((6, "tcp", &[1, 187][..]),
+            (segments[1].code(), segments[1].name(), segments[1].data()));
+        assert_eq!((478, "wss", &[][..]),
+            (segments[2].code(), segments[2].name(), segments[2].data()));
+        assert_eq!(Segment::Dns4("example.com".to_owned()), segments[0].to_owned());
+    }
+
+    #[test]
+    fn round_trip() {
+        let s = "/ip6/::1/udp/4001/quic-v1/ipfs/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC";
+        let bytes = encode(s);
+        let addr = MultiAddrRef::new(&bytes).unwrap();
+        assert_eq!(s.parse::<MultiAddr>().unwrap(), addr.to_owned());
+        assert_eq!(s, addr.to_string());
+        assert_eq!(&bytes[..], addr.as_bytes());
+    }
+
+    #[test]
+    fn unknown() {
+        let bytes = [6, 0, 22, 0x80, 0x80, 0x04, 1, 2, 3];
+        assert_eq!(
+            MultiAddrRef::new(&bytes).unwrap_err().to_string(),
+            "Unknown protocol code 65536, its value length can't be determined");
+    }
+
+    #[test]
+    fn registry() {
+        let bytes = [0x81, 0x80, 0xc0, 0x01, 3, b'f', b'o', b'o', 6, 0x0f, 0xa1];
+        assert!(MultiAddrRef::new(&bytes).is_err());
+
+        let mut registry = Registry::new();
+        registry.register(Label).unwrap();
+        let addr = MultiAddrRef::with_registry(&bytes, &registry).unwrap();
+        let label = addr.segments().next().unwrap();
+        assert_eq!((0x300001, "unknown", &b"foo"[..]), (label.code(), label.name(), label.data()));
+        assert_eq!("/label/foo/tcp/4001", addr.to_string());
+        assert_eq!(registry.read_multiaddr(&mut &bytes[..]).unwrap(), addr.to_owned());
+    }
+
+    #[test]
+    fn truncated() {
+        assert!(MultiAddrRef::new(&[4, 1, 2, 3]).is_err());
+        assert!(MultiAddrRef::new(&[53, 5, b'a']).is_err());
+    }
+
+    #[test]
+    fn invalid_value() {
+        assert!(MultiAddrRef::new(&[53, 2, 0xff, 0xfe]).is_err());
+        assert!(MultiAddrRef::new(&[42, 0]).is_err());
+    }
+}
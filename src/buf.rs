@@ -0,0 +1,269 @@
+use std::{ error, fmt, io };
+
+use bytes::{ Buf, BufMut };
+
+use { MultiAddr, Segment };
+use decoder::{ Frame, frame };
+use protocol::EMPTY;
+use read::read_segment_value;
+use write::write_single_segment;
+use Segment::*;
+
+/// The reason a `MultiAddr` couldn't be decoded from a `Buf`.
+#[derive(Debug)]
+pub enum BufDecodeError {
+    /// The buffer ended part way through a segment.
+    Truncated {
+        /// The byte offset of the truncated segment.
+        offset: usize,
+        /// The minimum number of further bytes needed to complete it.
+        needed: usize,
+    },
+
+    /// The segment at this byte offset wasn't valid, see
+    /// `ReadMultiAddr::read_multiaddr`.
+    Invalid {
+        /// The byte offset of the invalid segment.
+        offset: usize,
+        /// The error decoding the segment.
+        error: io::Error,
+    },
+}
+
+fn put_varint<B: BufMut>(buf: &mut B, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+fn put_prefixed<B: BufMut>(buf: &mut B, bytes: &[u8]) {
+    put_varint(buf, bytes.len() as u64);
+    buf.put_slice(bytes);
+}
+
+fn put_segment<B: BufMut>(buf: &mut B, segment: &Segment) {
+    match *segment {
+        Ipfs(_) | Certhash(_) => {
+            // mhash only supports writing through io::Write
+            let mut bytes = Vec::with_capacity(segment.encoded_len());
+            write_single_segment(&mut bytes, segment)
+                .expect("writing to a Vec cannot fail");
+            buf.put_slice(&bytes);
+            return;
+        }
+        _ => put_varint(buf, segment.code()),
+    }
+    match *segment {
+        IP4(ref addr) => buf.put_slice(&addr.octets()),
+        IP6(ref addr) => buf.put_slice(&addr.octets()),
+        Memory(id) => buf.put_u64(id),
+        Ipcidr(mask) => buf.put_u8(mask),
+        Udp(port) | Dccp(port) | Sctp(port) | Tcp(port) => buf.put_u16(port),
+        Ip6zone(ref name) | Dns(ref name) | Dns4(ref name) | Dns6(ref name)
+            | Dnsaddr(ref name) | Sni(ref name) | Unix(ref name) | HttpPath(ref name) =>
+            put_prefixed(buf, name.as_bytes()),
+        Onion(ref hash, port) => {
+            buf.put_slice(hash);
+            buf.put_u16(port);
+        }
+        Onion3(ref key, port) => {
+            buf.put_slice(key);
+            buf.put_u16(port);
+        }
+        Garlic64(ref bytes) | Garlic32(ref bytes) => put_prefixed(buf, bytes),
+        Unknown(_, ref data) => buf.put_slice(data),
+        Ipfs(_) | Certhash(_) | Udt | Utp | Http | Https | Quic | QuicV1 | WebTransport
+            | Ws | Wss | Tls | Noise | Plaintextv2 | P2pCircuit | WebRTC | WebRTCDirect
+            | P2pStardust | P2pWebRTCDirect | P2pWebRTCStar | P2pWebSocketStar => {
+        }
+    }
+}
+
+impl MultiAddr {
+    /// Encode this `MultiAddr` into the given buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer has less than `encoded_len()` bytes of remaining
+    /// capacity, as `BufMut::put_slice` does.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate bytes;
+    /// extern crate maddr;
+    ///
+    /// use bytes::BytesMut;
+    /// use maddr::MultiAddr;
+    ///
+    /// # fn main() {
+    /// let addr: MultiAddr = "/ip4/1.2.3.4/tcp/22".parse().unwrap();
+    /// let mut buffer = BytesMut::with_capacity(addr.encoded_len());
+    /// addr.encode_to(&mut buffer);
+    /// assert_eq!(&[4, 1, 2, 3, 4, 6, 0, 22], &buffer[..]);
+    /// # }
+    /// ```
+    pub fn encode_to<B: BufMut>(&self, buf: &mut B) {
+        let len = self.encoded_len();
+        assert!(
+            buf.remaining_mut() >= len,
+            "buffer too small to encode multiaddr, remaining: {}, needed: {}",
+            buf.remaining_mut(), len);
+        for segment in self.segments() {
+            put_segment(buf, segment);
+        }
+    }
+
+    /// Decode a `MultiAddr` from the given buffer, consuming all its remaining
+    /// bytes as there's no way to detect the end of an encoded `MultiAddr`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BufDecodeError::Truncated` if the buffer ends part way
+    /// through a segment, or `BufDecodeError::Invalid` if a segment isn't
+    /// valid, see `ReadMultiAddr::read_multiaddr`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// extern crate bytes;
+    /// extern crate maddr;
+    ///
+    /// use bytes::Bytes;
+    /// use maddr::{ BufDecodeError, MultiAddr, Segment };
+    ///
+    /// # fn main() {
+    /// let mut buffer = Bytes::from_static(&[6, 0, 22]);
+    /// assert_eq!(
+    ///     MultiAddr::from(Segment::Tcp(22)),
+    ///     MultiAddr::decode_from(&mut buffer).unwrap());
+    ///
+    /// let mut buffer = Bytes::from_static(&[6, 0, 22, 4, 1]);
+    /// match MultiAddr::decode_from(&mut buffer) {
+    ///     Err(BufDecodeError::Truncated { offset: 3, needed: 3 }) => (),
+    ///     other => panic!("unexpected {:?}", other),
+    /// }
+    /// # }
+    /// ```
+    pub fn decode_from<B: Buf>(buf: &mut B) -> Result<MultiAddr, BufDecodeError> {
+        // Segments may be split across chunks, copying into a single chunk is
+        // free for contiguous buffers such as `Bytes`
+        let remaining = buf.remaining();
+        let bytes = buf.copy_to_bytes(remaining);
+        let mut segments = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let invalid = |error| BufDecodeError::Invalid { offset: offset, error: error };
+            let rest = &bytes[offset..];
+            let (code, value, end) = match try!(frame(rest, &EMPTY).map_err(&invalid)) {
+                Frame::Complete { code, value, end, .. } => (code, value, end),
+                Frame::Incomplete(needed) => {
+                    return Err(BufDecodeError::Truncated { offset: offset, needed: needed });
+                }
+            };
+            segments.push(try!(read_segment_value(code, &rest[value..end], &EMPTY).map_err(&invalid)));
+            offset += end;
+        }
+        Ok(MultiAddr::new(segments))
+    }
+}
+
+impl fmt::Display for BufDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BufDecodeError::Truncated { offset, needed } =>
+                write!(f, "segment at byte {} truncated, needs {} more bytes", offset, needed),
+            BufDecodeError::Invalid { offset, ref error } =>
+                write!(f, "invalid segment at byte {}: {}", offset, error),
+        }
+    }
+}
+
+impl error::Error for BufDecodeError {
+    fn description(&self) -> &str {
+        match *self {
+            BufDecodeError::Truncated { .. } => "truncated segment",
+            BufDecodeError::Invalid { .. } => "invalid segment",
+        }
+    }
+}
+
+impl From<BufDecodeError> for io::Error {
+    fn from(err: BufDecodeError) -> io::Error {
+        let kind = match err {
+            BufDecodeError::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+            BufDecodeError::Invalid { ref error, .. } => error.kind(),
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{ Buf, BufMut, Bytes, BytesMut };
+
+    use { BufDecodeError, MultiAddr, WriteMultiAddr };
+
+    #[test]
+    fn round_trip() {
+        let addr: MultiAddr = "/dns4/example.com/tcp/443/wss/p2p-circuit".parse().unwrap();
+        let mut buffer = BytesMut::new();
+        buffer.put_u8(0xff);
+        addr.encode_to(&mut buffer);
+        assert_eq!(addr.encoded_len() + 1, buffer.len());
+
+        let mut buffer = buffer.freeze();
+        assert_eq!(0xff, buffer.get_u8());
+        assert_eq!(addr, MultiAddr::decode_from(&mut buffer).unwrap());
+        assert_eq!(0, buffer.remaining());
+    }
+
+    #[test]
+    fn matches_write() {
+        let addr: MultiAddr = concat!(
+            "/ip6zone/eth0/ip6/::1/udp/4001/quic-v1/memory/1234",
+            "/onion/timaq4ygg2iegci7:1234/unix/tmp/p2p.sock",
+            "/ipfs/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC").parse().unwrap();
+        let mut expected = Vec::new();
+        expected.write_multiaddr(&addr).unwrap();
+        let mut buffer = BytesMut::new();
+        addr.encode_to(&mut buffer);
+        assert_eq!(expected, &buffer[..]);
+    }
+
+    #[test]
+    fn chained() {
+        let mut buffer = Bytes::from_static(&[4, 1, 2]).chain(Bytes::from_static(&[3, 4]));
+        assert_eq!(
+            "/ip4/1.2.3.4".parse::<MultiAddr>().unwrap(),
+            MultiAddr::decode_from(&mut buffer).unwrap());
+    }
+
+    #[test]
+    fn truncated() {
+        let mut buffer = Bytes::from_static(&[6, 0, 22, 53, 5, b'a']);
+        match MultiAddr::decode_from(&mut buffer) {
+            Err(BufDecodeError::Truncated { offset: 3, needed: 4 }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let mut buffer = Bytes::from_static(&[6, 0, 22, 53, 2, 0xff, 0xfe]);
+        match MultiAddr::decode_from(&mut buffer) {
+            Err(BufDecodeError::Invalid { offset: 3, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_small() {
+        let addr: MultiAddr = "/ip4/1.2.3.4".parse().unwrap();
+        let mut buffer = [0; 4];
+        addr.encode_to(&mut &mut buffer[..]);
+    }
+}
@@ -0,0 +1,378 @@
+use std::cmp;
+use std::io;
+use std::sync::Arc;
+
+use { DecodeOptions, Registry, Segment, Size };
+use protocol::EMPTY;
+use protocols::{ self, ProtocolInfo };
+use read::read_segment_value;
+
+/// Incrementally decodes the segments of a binary encoded `MultiAddr` from
+/// chunks of bytes as they arrive, e.g. from a non-blocking socket.
+///
+/// Bytes are buffered until a whole segment is available, so input is never
+/// lost however it is split into chunks. Segments with a code that is neither
+/// built in nor in the decoder's registry are an error, as their length can't
+/// be determined.
+///
+/// By default a segment may declare any length and is buffered until it is
+/// complete, for untrusted input use `with_options` to limit this, e.g. with
+/// `DecodeOptions::strict`.
+///
+/// After an error is returned the decoder is left in an unspecified state
+/// and shouldn't be used further.
+///
+/// # Examples
+///
+/// ```rust
+/// use maddr::{ Decoded, Segment, SegmentDecoder };
+///
+/// let mut decoder = SegmentDecoder::new();
+/// decoder.push(&[6, 0]);
+/// assert_eq!(Decoded::NeedMore(1), decoder.decode().unwrap());
+/// decoder.push(&[22, 4, 1]);
+/// assert_eq!(Decoded::Segment(Segment::Tcp(22)), decoder.decode().unwrap());
+/// assert_eq!(Decoded::NeedMore(3), decoder.decode().unwrap());
+/// assert!(decoder.decode_eof().is_err());
+/// ```
+#[derive(Debug, Default)]
+pub struct SegmentDecoder {
+    buffer: Vec<u8>,
+    registry: Option<Arc<Registry>>,
+    options: DecodeOptions,
+    offset: usize,
+    segments: usize,
+}
+
+/// The result of attempting to decode a segment from a `SegmentDecoder`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Decoded {
+    /// A complete segment was decoded and removed from the buffer.
+    Segment(Segment),
+
+    /// At least this many more bytes are needed before the next segment can
+    /// be decoded.
+    NeedMore(usize),
+}
+
+/// The code and value length of the first segment of a buffer, the value
+/// (including any length prefix) starts at `value` and the data (excluding
+/// any length prefix) of `length` bytes starts at `data`.
+#[derive(Debug)]
+pub(crate) struct Header {
+    pub(crate) code: u64,
+    pub(crate) value: usize,
+    pub(crate) data: usize,
+    pub(crate) length: u64,
+}
+
+/// How the first segment of a buffer is laid out, as determined from its code
+/// and any length prefix.
+#[derive(Debug)]
+#[allow(variant_size_differences)]
+pub(crate) enum Frame {
+    /// A complete segment, the value (including any length prefix) starts at
+    /// `value`, the data (excluding any length prefix) starts at `data` and
+    /// the segment ends at `end`.
+    Complete { code: u64, value: usize, data: usize, end: usize },
+
+    /// At least this many more bytes are needed to complete the segment.
+    Incomplete(usize),
+}
+
+pub(crate) fn protocol_info(code: u64) -> Option<&'static ProtocolInfo> {
+    // Older versions of the protocol table used 17 for udp
+    protocols::by_code(if code == 17 { protocols::UDP.code } else { code })
+}
+
+/// Read a varint from the start of the buffer, returning its value and
+/// encoded length, or `None` if the buffer ends before the varint does.
+pub(crate) fn read_varint(bytes: &[u8]) -> io::Result<Option<(u64, usize)>> {
+    let mut value = 0;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if i > 9 || (i == 9 && byte > 1) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Varint overflows u64"));
+        }
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+    Ok(None)
+}
+
+/// Read the header of the first segment in the buffer, or `None` if the buffer
+/// ends before the header does, segments with a code that is neither built in
+/// nor in the registry are an error as their length can't be determined.
+pub(crate) fn header(bytes: &[u8], registry: &Registry) -> io::Result<Option<Header>> {
+    let (code, value) = match try!(read_varint(bytes)) {
+        Some(varint) => varint,
+        None => return Ok(None),
+    };
+    let size = match protocol_info(code) {
+        Some(protocol) => protocol.size,
+        None => match registry.get(code) {
+            Some(protocol) => protocol.size(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unknown protocol code {}, its value length can't be determined", code)));
+            }
+        },
+    };
+    let (data, length) = match size {
+        Size::Fixed(size) => (value, size as u64),
+        Size::LengthPrefixed => match try!(read_varint(&bytes[value..])) {
+            Some((length, prefix)) => (value + prefix, length),
+            None => return Ok(None),
+        },
+    };
+    Ok(Some(Header { code: code, value: value, data: data, length: length }))
+}
+
+/// Determine the layout of the first segment in the buffer without decoding
+/// its value, see `header`.
+pub(crate) fn frame(bytes: &[u8], registry: &Registry) -> io::Result<Frame> {
+    match try!(header(bytes, registry)) {
+        Some(header) => frame_header(bytes, &header),
+        None => Ok(Frame::Incomplete(1)),
+    }
+}
+
+/// Determine the layout of the segment with the given header, see `frame`.
+pub(crate) fn frame_header(bytes: &[u8], header: &Header) -> io::Result<Frame> {
+    let Header { code, value, data, length } = *header;
+    match (data as u64).checked_add(length) {
+        Some(end) if end <= bytes.len() as u64 => {
+            Ok(Frame::Complete { code: code, value: value, data: data, end: end as usize })
+        }
+        Some(end) => {
+            let needed = cmp::min(end - bytes.len() as u64, usize::max_value() as u64);
+            Ok(Frame::Incomplete(needed as usize))
+        }
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "Segment length overflows u64")),
+    }
+}
+
+impl SegmentDecoder {
+    /// Create a new decoder with an empty buffer.
+    pub fn new() -> SegmentDecoder {
+        SegmentDecoder::default()
+    }
+
+    /// Use the protocols in the given registry, in addition to the built in
+    /// protocols, to decode segments.
+    pub fn with_registry(self, registry: Arc<Registry>) -> SegmentDecoder {
+        SegmentDecoder { registry: Some(registry), ..self }
+    }
+
+    /// Check each segment against the given options as soon as its header is
+    /// available, so a segment longer than the maximum segment length is
+    /// rejected without waiting for the rest of it. The maximum length and
+    /// number of segments apply to all the input decoded.
+    pub fn with_options(self, options: DecodeOptions) -> SegmentDecoder {
+        SegmentDecoder { options: options, ..self }
+    }
+
+    /// Add a chunk of input to the buffer.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Get the number of buffered bytes that haven't been decoded yet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Attempt to decode the next segment from the buffered input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the buffered input can't be
+    /// the start of a valid segment, wrapping a `DecodeError` if the segment
+    /// violates the decoder's options.
+    pub fn decode(&mut self) -> io::Result<Decoded> {
+        match try!(self.frame()) {
+            Frame::Complete { code, value, end, .. } => {
+                Ok(Decoded::Segment(try!(self.take(code, value, end))))
+            }
+            Frame::Incomplete(needed) => Ok(Decoded::NeedMore(needed)),
+        }
+    }
+
+    /// Decode the next segment from the buffered input once the end of the
+    /// input has been reached, returns `None` once the buffer is empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `UnexpectedEof` if the buffer ends part way
+    /// through a segment, or `InvalidData` if it isn't a valid segment.
+    pub fn decode_eof(&mut self) -> io::Result<Option<Segment>> {
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        match try!(self.frame()) {
+            Frame::Complete { code, value, end, .. } => {
+                Ok(Some(try!(self.take(code, value, end))))
+            }
+            Frame::Incomplete(_) => {
+                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated segment"))
+            }
+        }
+    }
+
+    fn registry(&self) -> &Registry {
+        self.registry.as_ref().map_or(&EMPTY, |registry| registry)
+    }
+
+    fn frame(&self) -> io::Result<Frame> {
+        match try!(header(&self.buffer, self.registry())) {
+            Some(header) => {
+                try!(self.options.check_header(&self.buffer, &header, self.offset, self.segments));
+                frame_header(&self.buffer, &header)
+            }
+            None => Ok(Frame::Incomplete(1)),
+        }
+    }
+
+    fn take(&mut self, code: u64, value: usize, end: usize) -> io::Result<Segment> {
+        let segment = try!(read_segment_value(code, &self.buffer[value..end], self.registry()));
+        let _ = self.buffer.drain(..end);
+        self.offset += end;
+        self.segments += 1;
+        Ok(segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ error, fmt, io };
+    use std::sync::Arc;
+
+    use { DecodeError, DecodeOptions, MultiAddr, Protocol, Registry, Segment, Size, WriteMultiAddr };
+    use super::{ Decoded, SegmentDecoder };
+
+    #[derive(Debug)]
+    struct Label;
+
+    impl Protocol for Label {
+        fn code(&self) -> u64 { 0x300001 }
+        fn name(&self) -> &str { "label" }
+        fn size(&self) -> Size { Size::LengthPrefixed }
+        fn parse_value(&self, value: &str) -> Result<Vec<u8>, Box<dyn error::Error + Send + Sync>> {
+            Ok(value.as_bytes().to_vec())
+        }
+        fn fmt_value(&self, _: &[u8], _: &mut fmt::Formatter) -> fmt::Result {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn byte_at_a_time() {
+        let addr: MultiAddr = "/dns4/example.com/tcp/443/wss/p2p-circuit".parse().unwrap();
+        let mut bytes = Vec::new();
+        bytes.write_multiaddr(&addr).unwrap();
+
+        let mut decoder = SegmentDecoder::new();
+        let mut segments = Vec::new();
+        for byte in bytes {
+            decoder.push(&[byte]);
+            while let Decoded::Segment(segment) = decoder.decode().unwrap() {
+                segments.push(segment);
+            }
+        }
+        while let Some(segment) = decoder.decode_eof().unwrap() {
+            segments.push(segment);
+        }
+        assert_eq!(addr, MultiAddr::new(segments));
+        assert_eq!(0, decoder.buffered());
+    }
+
+    #[test]
+    fn need_more() {
+        let mut decoder = SegmentDecoder::new();
+        assert_eq!(Decoded::NeedMore(1), decoder.decode().unwrap());
+        decoder.push(&[0b10010000]);
+        assert_eq!(Decoded::NeedMore(1), decoder.decode().unwrap());
+        decoder.push(&[0b00000011]);
+        assert_eq!(Decoded::NeedMore(1), decoder.decode().unwrap());
+        decoder.push(&[5, b'/']);
+        assert_eq!(Decoded::NeedMore(4), decoder.decode().unwrap());
+        decoder.push(&[b't', b'm', b'p', b'/', 41]);
+        assert_eq!(
+            Decoded::Segment(Segment::Unix("/tmp/".to_owned())),
+            decoder.decode().unwrap());
+        assert_eq!(Decoded::NeedMore(16), decoder.decode().unwrap());
+        decoder.push(&[0; 15]);
+        assert_eq!(Decoded::NeedMore(1), decoder.decode().unwrap());
+        assert_eq!(16, decoder.buffered());
+        decoder.push(&[0]);
+        assert_eq!(
+            Decoded::Segment(Segment::IP6("::".parse().unwrap())),
+            decoder.decode().unwrap());
+        assert_eq!(0, decoder.buffered());
+    }
+
+    #[test]
+    fn registry() {
+        let bytes = [0x81, 0x80, 0xc0, 0x01, 3, b'f', b'o', b'o', 6, 0x0f, 0xa1];
+        let mut decoder = SegmentDecoder::new();
+        decoder.push(&bytes);
+        assert!(decoder.decode().is_err());
+
+        let mut registry = Registry::new();
+        registry.register(Label).unwrap();
+        let mut decoder = SegmentDecoder::new().with_registry(Arc::new(registry));
+        decoder.push(&bytes[..6]);
+        assert_eq!(Decoded::NeedMore(2), decoder.decode().unwrap());
+        decoder.push(&bytes[6..]);
+        assert_eq!(
+            Decoded::Segment(Segment::Unknown(0x300001, vec![3, b'f', b'o', b'o'])),
+            decoder.decode().unwrap());
+        assert_eq!(Decoded::Segment(Segment::Tcp(4001)), decoder.decode().unwrap());
+    }
+
+    #[test]
+    fn options() {
+        let options = DecodeOptions::new().with_max_segment_length(4).with_max_segments(2);
+        let mut decoder = SegmentDecoder::new().with_options(options);
+        decoder.push(&[6, 0, 22, 53, 0xff, 0xff, 0x03]);
+        assert_eq!(Decoded::Segment(Segment::Tcp(22)), decoder.decode().unwrap());
+        let err = decoder.decode().unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!(
+            Some(&DecodeError::SegmentTooLong { index: 1, length: 65535, max_segment_length: 4 }),
+            err.get_ref().and_then(|err| err.downcast_ref()));
+
+        let mut decoder = SegmentDecoder::new().with_options(options);
+        decoder.push(&[6, 0, 22, 6, 0, 23, 6]);
+        assert_eq!(Decoded::Segment(Segment::Tcp(22)), decoder.decode().unwrap());
+        assert_eq!(Decoded::Segment(Segment::Tcp(23)), decoder.decode().unwrap());
+        let err = decoder.decode().unwrap_err();
+        assert_eq!(
+            Some(&DecodeError::TooManySegments { max_segments: 2 }),
+            err.get_ref().and_then(|err| err.downcast_ref()));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut decoder = SegmentDecoder::new();
+        decoder.push(&[6, 0]);
+        assert_eq!(
+            decoder.decode_eof().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut decoder = SegmentDecoder::new();
+        decoder.push(&[53, 2, 0xff, 0xfe]);
+        assert!(decoder.decode().is_err());
+        let mut decoder = SegmentDecoder::new();
+        decoder.push(&[0xff; 11]);
+        assert!(decoder.decode().is_err());
+        let mut decoder = SegmentDecoder::new();
+        decoder.push(&[0b11100111, 0b00000111]);
+        assert!(decoder.decode().is_err());
+    }
+}
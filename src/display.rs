@@ -1,17 +1,143 @@
 use std::fmt;
 
-use { Segment, MultiAddr };
+use data_encoding::{ BASE32_NOPAD, BASE64, HEXLOWER };
+use mhash::WriteMultiHash;
+use multibase::{ self, Base };
 
-impl fmt::Display for Segment {
+use { Segment, MultiAddr, Registry, Size };
+
+/// Options for how to display a `MultiAddr`.
+///
+/// # Examples
+///
+/// ```rust
+/// use maddr::{ DisplayMode, MultiAddr };
+///
+/// let addr: MultiAddr = "/ipfs/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC".parse().unwrap();
+/// assert_eq!(
+///     "/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
+///     addr.display(DisplayMode::P2p).to_string());
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DisplayMode {
+    /// Display `Segment::Ipfs` using the legacy `/ipfs/` name, this is the
+    /// mode used by the `Display` implementations for compatibility with older
+    /// peers.
+    Ipfs,
+
+    /// Display `Segment::Ipfs` using the `/p2p/` name from the current
+    /// multicodec table.
+    P2p,
+}
+
+impl Default for DisplayMode {
+    fn default() -> DisplayMode {
+        DisplayMode::Ipfs
+    }
+}
+
+/// Helper struct for displaying a `MultiAddr` with a specific `DisplayMode`,
+/// created by [`MultiAddr::display`](struct.MultiAddr.html#method.display).
+#[derive(Debug)]
+pub struct MultiAddrDisplay<'a> {
+    multiaddr: &'a MultiAddr,
+    mode: DisplayMode,
+    registry: Option<&'a Registry>,
+}
+
+/// Helper struct for displaying a `Segment` with a specific `DisplayMode`,
+/// created by [`Segment::display`](enum.Segment.html#method.display).
+#[derive(Debug)]
+pub struct SegmentDisplay<'a> {
+    segment: &'a Segment,
+    mode: DisplayMode,
+    registry: Option<&'a Registry>,
+}
+
+impl MultiAddr {
+    /// Get an object that will display this `MultiAddr` using the given mode.
+    pub fn display<'a>(&'a self, mode: DisplayMode) -> MultiAddrDisplay<'a> {
+        MultiAddrDisplay { multiaddr: self, mode: mode, registry: None }
+    }
+}
+
+impl Segment {
+    /// Get an object that will display this `Segment` using the given mode.
+    pub fn display<'a>(&'a self, mode: DisplayMode) -> SegmentDisplay<'a> {
+        SegmentDisplay { segment: self, mode: mode, registry: None }
+    }
+}
+
+impl<'a> MultiAddrDisplay<'a> {
+    /// Use the protocols in the given registry to display any
+    /// `Segment::Unknown` segments that have a registered code.
+    pub fn with_registry(self, registry: &'a Registry) -> MultiAddrDisplay<'a> {
+        MultiAddrDisplay { registry: Some(registry), ..self }
+    }
+}
+
+impl<'a> SegmentDisplay<'a> {
+    /// Use the protocols in the given registry to display a
+    /// `Segment::Unknown` that has a registered code.
+    pub fn with_registry(self, registry: &'a Registry) -> SegmentDisplay<'a> {
+        SegmentDisplay { registry: Some(registry), ..self }
+    }
+}
+
+/// Write a string value, escaping `%`, control characters and any of the
+/// given extra characters so that the parser can recover the exact value.
+pub(crate) fn write_escaped(f: &mut fmt::Formatter, value: &str, extra: &[char]) -> fmt::Result {
+    for c in value.chars() {
+        if c == '%' || c.is_control() || extra.contains(&c) {
+            let mut buffer = [0; 4];
+            for byte in c.encode_utf8(&mut buffer).bytes() {
+                try!(write!(f, "%{:02X}", byte));
+            }
+        } else {
+            try!(write!(f, "{}", c));
+        }
+    }
+    Ok(())
+}
+
+impl<'a> fmt::Display for SegmentDisplay<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        try!(write!(f, "/{}", self.name()));
-        match *self {
+        if let (&Segment::Unknown(code, ref data), Some(registry)) = (self.segment, self.registry) {
+            if let Some(protocol) = registry.get(code) {
+                if let Some(value) = ::protocol::split_value(protocol, data) {
+                    try!(write!(f, "/{}", protocol.name()));
+                    if protocol.size() != Size::Fixed(0) {
+                        try!(f.write_str("/"));
+                        try!(protocol.fmt_value(value, f));
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        match (self.segment, self.mode) {
+            (&Segment::Ipfs(_), DisplayMode::P2p) => {
+                try!(f.write_str("/p2p"));
+            }
+            (&Segment::Unknown(code, _), _) => {
+                try!(write!(f, "/x-{}", code));
+            }
+            _ => {
+                try!(write!(f, "/{}", self.segment.name()));
+            }
+        }
+        match *self.segment {
             Segment::IP4(ref addr) => {
                 try!(write!(f, "/{}", addr));
             }
             Segment::IP6(ref addr) => {
                 try!(write!(f, "/{}", addr));
             }
+            Segment::Ipcidr(ref mask) => {
+                try!(write!(f, "/{}", mask));
+            }
+            Segment::Memory(ref id) => {
+                try!(write!(f, "/{}", id));
+            }
             Segment::Udp(ref port)
             | Segment::Dccp(ref port)
             | Segment::Sctp(ref port)
@@ -21,25 +147,103 @@ impl fmt::Display for Segment {
             Segment::Ipfs(ref multihash) => {
                 try!(write!(f, "/{}", multihash));
             }
+            Segment::Certhash(ref multihash) => {
+                let mut bytes = Vec::with_capacity(multihash.output_len());
+                try!(bytes.write_multihash(multihash).map_err(|_| fmt::Error));
+                try!(write!(f, "/{}", multibase::encode(Base::Base64Url, bytes)));
+            }
+            Segment::Dns(ref name)
+            | Segment::Dns4(ref name)
+            | Segment::Dns6(ref name)
+            | Segment::Dnsaddr(ref name)
+            | Segment::Sni(ref name)
+            | Segment::Ip6zone(ref name) => {
+                try!(write!(f, "/{}", name));
+            }
+            Segment::Onion(ref hash, port) => {
+                let addr = BASE32_NOPAD.encode(hash).to_ascii_lowercase();
+                try!(write!(f, "/{}:{}", addr, port));
+            }
+            Segment::Onion3(ref key, port) => {
+                let addr = BASE32_NOPAD.encode(key).to_ascii_lowercase();
+                try!(write!(f, "/{}:{}", addr, port));
+            }
+            Segment::Garlic64(ref bytes) => {
+                let addr = BASE64.encode(bytes).replace('+', "-").replace('/', "~");
+                try!(write!(f, "/{}", addr));
+            }
+            Segment::Garlic32(ref bytes) => {
+                let addr = BASE32_NOPAD.encode(bytes).to_ascii_lowercase();
+                try!(write!(f, "/{}", addr));
+            }
+            Segment::Unix(ref path) => {
+                // The path takes up the rest of the address so can contain
+                // unescaped slashes
+                if !path.starts_with('/') {
+                    try!(f.write_str("/"));
+                }
+                try!(write_escaped(f, path, &[]));
+            }
+            Segment::HttpPath(ref path) => {
+                try!(f.write_str("/"));
+                try!(write_escaped(f, path, &['/', '?', '#']));
+            }
+            Segment::Unknown(_, ref data) => {
+                // Always write the value, even if empty, so the following
+                // segment isn't parsed as the value
+                try!(write!(f, "/{}", HEXLOWER.encode(data)));
+            }
             Segment::Udt
             | Segment::Utp
             | Segment::Http
-            | Segment::Https => {
+            | Segment::Https
+            | Segment::Quic
+            | Segment::QuicV1
+            | Segment::WebTransport
+            | Segment::Ws
+            | Segment::Wss
+            | Segment::Tls
+            | Segment::Noise
+            | Segment::Plaintextv2
+            | Segment::P2pCircuit
+            | Segment::WebRTC
+            | Segment::WebRTCDirect
+            | Segment::P2pStardust
+            | Segment::P2pWebRTCDirect
+            | Segment::P2pWebRTCStar
+            | Segment::P2pWebSocketStar => {
             }
         }
         Ok(())
     }
 }
 
-impl fmt::Display for MultiAddr {
+impl<'a> fmt::Display for MultiAddrDisplay<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for segment in self.segments() {
-            try!(write!(f, "{}", segment));
+        for segment in self.multiaddr.segments() {
+            let display = SegmentDisplay {
+                segment: segment,
+                mode: self.mode,
+                registry: self.registry,
+            };
+            try!(write!(f, "{}", display));
         }
         Ok(())
     }
 }
 
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.display(DisplayMode::default()), f)
+    }
+}
+
+impl fmt::Display for MultiAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.display(DisplayMode::default()), f)
+    }
+}
+
 impl fmt::Debug for MultiAddr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         try!(f.write_str("MultiAddr(\""));
@@ -64,7 +268,7 @@ mod tests {
 
     use mhash::{ MultiHash, MultiHashVariant };
 
-    use { MultiAddr, Segment };
+    use { DisplayMode, MultiAddr, Segment };
 
     #[test]
     fn ip4() {
@@ -81,6 +285,33 @@ mod tests {
             "/ip6/2a02:6b8::11:11");
     }
 
+    #[test]
+    fn ip6zone() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::Ip6zone("eth0".to_owned()),
+                Segment::IP6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            ]).to_string(),
+            "/ip6zone/eth0/ip6/fe80::1");
+    }
+
+    #[test]
+    fn ipcidr() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(10, 0, 0, 0)),
+                Segment::Ipcidr(8),
+            ]).to_string(),
+            "/ip4/10.0.0.0/ipcidr/8");
+    }
+
+    #[test]
+    fn memory() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Memory(1234)]).to_string(),
+            "/memory/1234");
+    }
+
     #[test]
     fn ipfs() {
         let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
@@ -93,4 +324,144 @@ mod tests {
             MultiAddr::new(vec![Segment::Ipfs(multihash)]).to_string(),
             "/ipfs/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC");
     }
+
+    #[test]
+    fn p2p() {
+        let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
+            213, 46, 187, 137, 216, 91, 2, 162,
+            132, 148, 130, 3, 166, 47, 242, 131,
+            137, 197, 124, 159, 66, 190, 236, 78,
+            194, 13, 183, 106, 104, 145, 28, 11,
+        ]).unwrap();
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Ipfs(multihash)]).display(DisplayMode::P2p).to_string(),
+            "/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC");
+    }
+
+    #[test]
+    fn webtransport() {
+        let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
+            213, 46, 187, 137, 216, 91, 2, 162,
+            132, 148, 130, 3, 166, 47, 242, 131,
+            137, 197, 124, 159, 66, 190, 236, 78,
+            194, 13, 183, 106, 104, 145, 28, 11,
+        ]).unwrap();
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(1, 2, 3, 4)),
+                Segment::Udp(4001),
+                Segment::QuicV1,
+                Segment::WebTransport,
+                Segment::Certhash(multihash),
+            ]).to_string(),
+            "/ip4/1.2.3.4/udp/4001/quic-v1/webtransport/certhash/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw");
+    }
+
+    #[test]
+    fn dns4() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::Dns4("example.com".to_owned()),
+                Segment::Tcp(4001),
+            ]).to_string(),
+            "/dns4/example.com/tcp/4001");
+    }
+
+    #[test]
+    fn tls_sni_ws() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(1, 2, 3, 4)),
+                Segment::Tcp(443),
+                Segment::Tls,
+                Segment::Sni("example.com".to_owned()),
+                Segment::Ws,
+            ]).to_string(),
+            "/ip4/1.2.3.4/tcp/443/tls/sni/example.com/ws");
+    }
+
+    #[test]
+    fn circuit_webrtc() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(1, 2, 3, 4)),
+                Segment::Udp(4001),
+                Segment::WebRTCDirect,
+                Segment::P2pCircuit,
+                Segment::WebRTC,
+            ]).to_string(),
+            "/ip4/1.2.3.4/udp/4001/webrtc-direct/p2p-circuit/webrtc");
+    }
+
+    #[test]
+    fn onion() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Onion(
+                [154, 24, 8, 115, 6, 54, 144, 67, 9, 31],
+                1234)]).to_string(),
+            "/onion/timaq4ygg2iegci7:1234");
+    }
+
+    #[test]
+    fn onion3() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Onion3([
+                173, 173, 236, 4, 11, 224, 71, 249, 101, 134, 104, 177,
+                26, 80, 79, 49, 85, 0, 31, 35, 26, 55, 245, 76, 68, 118,
+                192, 127, 180, 204, 19, 158, 215, 227, 3,
+            ], 1234)]).to_string(),
+            "/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234");
+    }
+
+    #[test]
+    fn garlic32() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Garlic32(vec![
+                239, 188, 212, 93, 12, 93, 199, 151, 129, 172, 111, 32, 234, 80, 85, 160,
+                54, 175, 180, 141, 69, 165, 46, 125, 104, 236, 125, 67, 56, 145, 158, 105,
+            ])]).to_string(),
+            "/garlic32/566niximlxdzpanmn4qouucvua3k7neniwss47li5r6ugoertzuq");
+    }
+
+    #[test]
+    fn unknown() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::Tcp(4001),
+                Segment::Unknown(999, vec![0xde, 0xad, 0xbe, 0xef]),
+            ]).to_string(),
+            "/tcp/4001/x-999/deadbeef");
+    }
+
+    #[test]
+    fn unknown_empty() {
+        let addr = MultiAddr::new(vec![Segment::Unknown(999, vec![]), Segment::Tcp(4001)]);
+        assert_eq!(addr.to_string(), "/x-999//tcp/4001");
+        assert_eq!(addr, addr.to_string().parse().unwrap());
+    }
+
+    #[test]
+    fn unix() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Unix("/tmp/p2p.sock".to_owned())]).to_string(),
+            "/unix/tmp/p2p.sock");
+    }
+
+    #[test]
+    fn unix_escaped() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Unix("/tmp/100%.sock".to_owned())]).to_string(),
+            "/unix/tmp/100%25.sock");
+    }
+
+    #[test]
+    fn unix_relative() {
+        // The path always starts with a slash in the string representation,
+        // so relative paths are made absolute
+        let addr = MultiAddr::new(vec![Segment::Unix("p2p.sock".to_owned())]);
+        assert_eq!("/unix/p2p.sock", addr.to_string());
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Unix("/p2p.sock".to_owned())]),
+            addr.to_string().parse().unwrap());
+    }
 }
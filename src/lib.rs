@@ -1,5 +1,3 @@
-#![recursion_limit = "1024"]
-
 #![allow(unknown_lints)] // for clippy
 #![warn(missing_copy_implementations)]
 #![warn(missing_debug_implementations)]
@@ -15,21 +13,65 @@
 //! [multiaddr]: https://github.com/multiformats/multiaddr
 //! [ipfs]: https://ipfs.io
 
-#[macro_use]
-extern crate error_chain;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+extern crate data_encoding;
+#[cfg(feature = "futures-io")]
+extern crate futures_io;
 extern crate mhash;
+extern crate multibase;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "tokio")]
+extern crate tokio;
 extern crate varmint;
 
+#[cfg(test)]
+extern crate futures;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_test;
+
 mod multiaddr;
 mod segment;
+mod borrowed;
+
+mod memory;
+mod options;
+mod packed;
+mod protocol;
+
+pub mod protocols;
 
+pub mod net;
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub mod async_io;
+
+#[cfg(feature = "bytes")]
+mod buf;
+mod decoder;
 mod display;
 mod parse;
 mod read;
+mod socket;
 mod write;
 
+#[cfg(feature = "serde")]
+mod serde_impls;
+
 pub use multiaddr::MultiAddr;
 pub use segment::Segment;
+pub use borrowed::{ MultiAddrRef, SegmentRef, SegmentRefs };
+
+pub use decoder::{ Decoded, SegmentDecoder };
+pub use display::{ DisplayMode, MultiAddrDisplay, SegmentDisplay };
+pub use memory::{ MemoryListener, MemoryStream };
+pub use options::{ DecodeError, DecodeOptions };
+pub use parse::{ ParseError, ValueError };
+pub use packed::{ PackedMultiAddr, PackedSegments };
+pub use protocol::{ Protocol, RegisterError, Registry, Size };
+pub use socket::{ SocketAddrError, Transport };
+#[cfg(feature = "bytes")]
+pub use buf::BufDecodeError;
 
 pub use read::ReadMultiAddr;
 pub use write::WriteMultiAddr;
@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Mutex;
+use std::sync::mpsc::{ self, Receiver, Sender };
+
+use { MultiAddr, Segment };
+
+/// The listeners currently bound to `/memory/<id>` addresses.
+static LISTENERS: Mutex<BTreeMap<u64, Sender<MemoryStream>>> = Mutex::new(BTreeMap::new());
+
+fn memory_id(addr: &MultiAddr) -> io::Result<u64> {
+    match *addr.segments() {
+        [Segment::Memory(id)] => Ok(id),
+        _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a memory address", addr))),
+    }
+}
+
+/// A listener for in-process connections to a `/memory/<id>` address.
+///
+/// This allows testing code that dials `MultiAddr`s without going through the
+/// network, connections are only possible from within the same process.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::{ Read, Write };
+/// use maddr::{ MemoryListener, MemoryStream };
+///
+/// let listener = MemoryListener::bind(&"/memory/0".parse().unwrap()).unwrap();
+/// let mut client = MemoryStream::connect(&listener.local_addr()).unwrap();
+/// let mut server = listener.accept().unwrap();
+///
+/// client.write_all(b"hello").unwrap();
+/// let mut buffer = [0; 5];
+/// server.read_exact(&mut buffer).unwrap();
+/// assert_eq!(b"hello", &buffer);
+/// ```
+#[derive(Debug)]
+pub struct MemoryListener {
+    id: u64,
+    incoming: Receiver<MemoryStream>,
+}
+
+/// A bidirectional in-process byte stream, created by connecting to a
+/// `MemoryListener`.
+#[derive(Debug)]
+pub struct MemoryStream {
+    sender: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl MemoryListener {
+    /// Bind a listener to the given `/memory/<id>` address, if the id is 0 an
+    /// unused id will be chosen, use `local_addr` to find out which.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the address is not a single
+    /// memory segment, or `AddrInUse` if another listener is bound to the
+    /// same id.
+    pub fn bind(addr: &MultiAddr) -> io::Result<MemoryListener> {
+        let id = try!(memory_id(addr));
+        let mut listeners = LISTENERS.lock().unwrap_or_else(|err| err.into_inner());
+        let id = if id == 0 {
+            (1..).find(|id| !listeners.contains_key(id)).expect("ran out of memory ids")
+        } else if listeners.contains_key(&id) {
+            return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is already bound", addr)));
+        } else {
+            id
+        };
+        let (sender, receiver) = mpsc::channel();
+        let _ = listeners.insert(id, sender);
+        Ok(MemoryListener { id: id, incoming: receiver })
+    }
+
+    /// The address this listener is bound to.
+    pub fn local_addr(&self) -> MultiAddr {
+        Segment::Memory(self.id).into()
+    }
+
+    /// Block until a new connection is made to this listener.
+    pub fn accept(&self) -> io::Result<MemoryStream> {
+        self.incoming.recv().map_err(|_| io::Error::new(
+                io::ErrorKind::NotConnected,
+                "listener has been unregistered"))
+    }
+}
+
+impl Drop for MemoryListener {
+    fn drop(&mut self) {
+        let mut listeners = LISTENERS.lock().unwrap_or_else(|err| err.into_inner());
+        let _ = listeners.remove(&self.id);
+    }
+}
+
+impl MemoryStream {
+    /// Connect to the `MemoryListener` bound to the given `/memory/<id>`
+    /// address.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the address is not a single
+    /// memory segment, or `ConnectionRefused` if there is no listener bound
+    /// to it.
+    pub fn connect(addr: &MultiAddr) -> io::Result<MemoryStream> {
+        let id = try!(memory_id(addr));
+        let listeners = LISTENERS.lock().unwrap_or_else(|err| err.into_inner());
+        let refused = || io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("no listener bound to {}", addr));
+        let listener = try!(listeners.get(&id).ok_or_else(&refused));
+        let (client, server) = MemoryStream::pair();
+        try!(listener.send(server).map_err(|_| refused()));
+        Ok(client)
+    }
+
+    fn pair() -> (MemoryStream, MemoryStream) {
+        let (a_sender, a_receiver) = mpsc::channel();
+        let (b_sender, b_receiver) = mpsc::channel();
+        let a = MemoryStream {
+            sender: a_sender,
+            receiver: b_receiver,
+            buffer: Vec::new(),
+            position: 0,
+        };
+        let b = MemoryStream {
+            sender: b_sender,
+            receiver: a_receiver,
+            buffer: Vec::new(),
+            position: 0,
+        };
+        (a, b)
+    }
+}
+
+impl io::Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.position == self.buffer.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.buffer = chunk;
+                    self.position = 0;
+                }
+                // The other end has been dropped
+                Err(_) => return Ok(0),
+            }
+        }
+        let length = ::std::cmp::min(buf.len(), self.buffer.len() - self.position);
+        buf[..length].copy_from_slice(&self.buffer[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+impl io::Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        try!(self.sender.send(buf.to_vec()).map_err(|_| io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "other end of memory stream has been dropped")));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ self, Read, Write };
+
+    use { MemoryListener, MemoryStream, MultiAddr, Segment };
+
+    #[test]
+    fn round_trip() {
+        let listener = MemoryListener::bind(&"/memory/0".parse().unwrap()).unwrap();
+        let mut client = MemoryStream::connect(&listener.local_addr()).unwrap();
+        let mut server = listener.accept().unwrap();
+
+        client.write_all(b"ping").unwrap();
+        let mut buffer = [0; 4];
+        server.read_exact(&mut buffer).unwrap();
+        assert_eq!(b"ping", &buffer);
+
+        server.write_all(b"pong").unwrap();
+        drop(server);
+        let mut buffer = Vec::new();
+        let _ = client.read_to_end(&mut buffer).unwrap();
+        assert_eq!(b"pong", &buffer[..]);
+    }
+
+    #[test]
+    fn addr_in_use() {
+        let listener = MemoryListener::bind(&"/memory/0".parse().unwrap()).unwrap();
+        let err = MemoryListener::bind(&listener.local_addr()).unwrap_err();
+        assert_eq!(io::ErrorKind::AddrInUse, err.kind());
+    }
+
+    #[test]
+    fn connection_refused() {
+        let addr = MultiAddr::from(Segment::Memory(u64::max_value()));
+        let err = MemoryStream::connect(&addr).unwrap_err();
+        assert_eq!(io::ErrorKind::ConnectionRefused, err.kind());
+    }
+
+    #[test]
+    fn invalid_addr() {
+        let addr = MultiAddr::from(Segment::Memory(1)) + Segment::Tcp(1);
+        let err = MemoryStream::connect(&addr).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+}
@@ -1,3 +1,5 @@
+use std::mem;
+use std::net::{ SocketAddr, SocketAddrV6 };
 use std::ops::Add;
 
 use Segment;
@@ -56,6 +58,100 @@ impl MultiAddr {
         &self.segments
     }
 
+    /// Append a segment to the end of this `MultiAddr`.
+    pub fn push(&mut self, segment: Segment) {
+        self.segments.push(segment);
+    }
+
+    /// Remove the last segment from this `MultiAddr` and return it, or `None`
+    /// if it is empty.
+    pub fn pop(&mut self) -> Option<Segment> {
+        self.segments.pop()
+    }
+
+    /// Replace the segment at `index` with the given segment, returning the
+    /// segment that was replaced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn replace(&mut self, index: usize, segment: Segment) -> Segment {
+        mem::replace(&mut self.segments[index], segment)
+    }
+
+    /// Returns whether the segments of `prefix` are a prefix of this
+    /// `MultiAddr`'s segments.
+    pub fn starts_with(&self, prefix: &MultiAddr) -> bool {
+        self.segments.starts_with(&prefix.segments)
+    }
+
+    /// Returns whether the segments of `suffix` are a suffix of this
+    /// `MultiAddr`'s segments.
+    pub fn ends_with(&self, suffix: &MultiAddr) -> bool {
+        self.segments.ends_with(&suffix.segments)
+    }
+
+    /// Return a new `MultiAddr` wrapping the given address in this one, i.e.
+    /// with its segments appended, as `Encapsulate` does in go-multiaddr.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::{ MultiAddr, Segment };
+    /// let addr: MultiAddr = "/ip4/1.2.3.4".parse().unwrap();
+    /// assert_eq!("/ip4/1.2.3.4/tcp/22", addr.encapsulate(Segment::Tcp(22)).to_string());
+    /// ```
+    pub fn encapsulate<T: Into<MultiAddr>>(&self, other: T) -> MultiAddr {
+        self.clone() + other
+    }
+
+    /// Return a new `MultiAddr` with the last occurrence of the given address
+    /// and everything after it removed, as `Decapsulate` does in
+    /// go-multiaddr. If the address doesn't occur, or is empty, returns a copy
+    /// of this `MultiAddr`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::MultiAddr;
+    /// let addr: MultiAddr = "/ip4/1.2.3.4/tcp/22/ws/tcp/22/ws".parse().unwrap();
+    /// let ws: MultiAddr = "/tcp/22/ws".parse().unwrap();
+    /// assert_eq!("/ip4/1.2.3.4/tcp/22/ws", addr.decapsulate(&ws).to_string());
+    /// ```
+    pub fn decapsulate(&self, other: &MultiAddr) -> MultiAddr {
+        if other.segments.is_empty() {
+            return self.clone();
+        }
+        let index = self.segments
+            .windows(other.segments.len())
+            .rposition(|window| window == &other.segments[..]);
+        self.truncated(index)
+    }
+
+    /// Return a new `MultiAddr` with the last segment of the given protocol
+    /// code and everything after it removed, as `decapsulateCode` does in
+    /// js-multiaddr. If no segment has the code returns a copy of this
+    /// `MultiAddr`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::{ MultiAddr, protocols };
+    /// let addr: MultiAddr = "/ip4/1.2.3.4/tcp/4001/ipfs/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ".parse().unwrap();
+    /// assert_eq!(
+    ///     "/ip4/1.2.3.4/tcp/4001",
+    ///     addr.decapsulate_code(protocols::P2P.code).to_string());
+    /// ```
+    pub fn decapsulate_code(&self, code: u64) -> MultiAddr {
+        let index = self.segments.iter().rposition(|segment| segment.code() == code);
+        self.truncated(index)
+    }
+
+    fn truncated(&self, index: Option<usize>) -> MultiAddr {
+        let end = index.unwrap_or(self.segments.len());
+        MultiAddr::new(self.segments[..end].to_vec())
+    }
+
     /// Attempt to split off the last component of this `MultiAddr`, if this
     /// address is empty will return `None`, otherwise returns tuple with a
     /// `MultiAddr` containing all except the last segment and the last segment
@@ -80,6 +176,63 @@ impl MultiAddr {
     pub fn split_off_last(mut self) -> Option<(MultiAddr, Segment)> {
         self.segments.pop().map(|tail| (self, tail))
     }
+
+    /// Attempt to split a circuit relay address around its last
+    /// `Segment::P2pCircuit`, if this address doesn't contain a circuit
+    /// segment will return `None`, otherwise returns a tuple with a
+    /// `MultiAddr` containing the segments addressing the relay and a
+    /// `MultiAddr` containing the segments addressing the destination.
+    ///
+    /// The destination part may be empty, `/<relay>/p2p-circuit` is used to
+    /// advertise that a node can be reached via the given relay.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::MultiAddr;
+    /// let addr: MultiAddr = "/ip4/1.2.3.4/tcp/4001/ipfs/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ/p2p-circuit/ipfs/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC".parse().unwrap();
+    /// let (relay, destination) = addr.split_circuit().unwrap();
+    /// assert_eq!(
+    ///     "/ip4/1.2.3.4/tcp/4001/ipfs/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ",
+    ///     relay.to_string());
+    /// assert_eq!(
+    ///     "/ipfs/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
+    ///     destination.to_string());
+    /// ```
+    pub fn split_circuit(mut self) -> Option<(MultiAddr, MultiAddr)> {
+        let index = self.segments.iter().rposition(|segment| *segment == Segment::P2pCircuit);
+        index.map(|index| {
+            let destination = self.segments.split_off(index + 1);
+            let _ = self.segments.pop();
+            (self, MultiAddr::new(destination))
+        })
+    }
+
+    /// Attempt to convert this `MultiAddr` into an IPv6 socket address, this
+    /// is only possible for addresses of the form
+    /// `[/ip6zone/<zone>]/ip6/<addr>/(tcp|udp)/<port>`, anything else will
+    /// return `None`.
+    ///
+    /// As `SocketAddrV6` can only represent a numeric scope id the zone must
+    /// be an interface index, an interface name will return `None`. If there
+    /// is no zone the scope id will be 0. See `to_transport_addr` for a
+    /// conversion that also supports IPv4 and reports why it failed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::MultiAddr;
+    /// let addr: MultiAddr = "/ip6zone/2/ip6/fe80::1/tcp/4001".parse().unwrap();
+    /// let socket_addr = addr.to_socket_addr_v6().unwrap();
+    /// assert_eq!(2, socket_addr.scope_id());
+    /// assert_eq!(4001, socket_addr.port());
+    /// ```
+    pub fn to_socket_addr_v6(&self) -> Option<SocketAddrV6> {
+        match self.to_transport_addr() {
+            Ok((SocketAddr::V6(socket_addr), _)) => Some(socket_addr),
+            _ => None,
+        }
+    }
 }
 
 impl<T> From<T> for MultiAddr where T: Into<Segment> {
@@ -99,7 +252,7 @@ impl<T> Add<T> for MultiAddr where T: Into<MultiAddr> {
 
 #[cfg(test)]
 mod tests {
-    use std::net::Ipv4Addr;
+    use std::net::{ Ipv4Addr, Ipv6Addr, SocketAddrV6 };
 
     use {MultiAddr, Segment};
 
@@ -119,4 +272,90 @@ mod tests {
             ]),
             MultiAddr::from(Ipv4Addr::new(1, 2, 3, 4)) + Segment::Tcp(22));
     }
+
+    #[test]
+    fn split_circuit() {
+        let addr = MultiAddr::from(Ipv4Addr::new(1, 2, 3, 4))
+            + Segment::Tcp(22)
+            + Segment::P2pCircuit
+            + Segment::Tcp(33)
+            + Segment::P2pCircuit
+            + Segment::WebRTC;
+        assert_eq!(
+            Some((
+                MultiAddr::from(Ipv4Addr::new(1, 2, 3, 4))
+                    + Segment::Tcp(22)
+                    + Segment::P2pCircuit
+                    + Segment::Tcp(33),
+                MultiAddr::from(Segment::WebRTC),
+            )),
+            addr.split_circuit());
+    }
+
+    #[test]
+    fn to_socket_addr_v6() {
+        let addr = MultiAddr::from(Segment::Ip6zone("3".to_owned()))
+            + Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)
+            + Segment::Udp(53);
+        assert_eq!(
+            Some(SocketAddrV6::new(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1), 53, 0, 3)),
+            addr.to_socket_addr_v6());
+    }
+
+    #[test]
+    fn to_socket_addr_v6_named_zone() {
+        let addr = MultiAddr::from(Segment::Ip6zone("eth0".to_owned()))
+            + Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)
+            + Segment::Udp(53);
+        assert_eq!(None, addr.to_socket_addr_v6());
+    }
+
+    #[test]
+    fn push_pop_replace() {
+        let mut addr = MultiAddr::from(Ipv4Addr::new(1, 2, 3, 4));
+        addr.push(Segment::Tcp(22));
+        assert_eq!(Segment::Tcp(22), addr.replace(1, Segment::Udp(53)));
+        assert_eq!(Some(Segment::Udp(53)), addr.pop());
+        assert_eq!(Some(Segment::IP4(Ipv4Addr::new(1, 2, 3, 4))), addr.pop());
+        assert_eq!(None, addr.pop());
+    }
+
+    #[test]
+    fn starts_ends_with() {
+        let addr = MultiAddr::from(Ipv4Addr::new(1, 2, 3, 4)) + Segment::Tcp(22);
+        assert!(addr.starts_with(&Ipv4Addr::new(1, 2, 3, 4).into()));
+        assert!(addr.ends_with(&Segment::Tcp(22).into()));
+        assert!(addr.starts_with(&MultiAddr::new(vec![])));
+        assert!(!addr.starts_with(&Segment::Tcp(22).into()));
+        assert!(!addr.ends_with(&(MultiAddr::from(Segment::Tcp(22)) + Segment::Tcp(22))));
+    }
+
+    #[test]
+    fn encapsulate_decapsulate() {
+        let ip4 = MultiAddr::from(Ipv4Addr::new(1, 2, 3, 4));
+        let tcp = MultiAddr::from(Segment::Tcp(22));
+        let addr = ip4.encapsulate(tcp.clone()).encapsulate(Segment::Ws);
+        assert_eq!(ip4.clone() + Segment::Tcp(22) + Segment::Ws, addr);
+        assert_eq!(ip4, addr.decapsulate(&tcp));
+        assert_eq!(addr, addr.decapsulate(&Segment::Udp(22).into()));
+        assert_eq!(addr, addr.decapsulate(&MultiAddr::new(vec![])));
+        assert_eq!(MultiAddr::new(vec![]), addr.decapsulate(&addr));
+    }
+
+    #[test]
+    fn decapsulate_code() {
+        let addr = MultiAddr::from(Segment::Tcp(22))
+            + Segment::P2pCircuit
+            + Segment::Tcp(33)
+            + Segment::Ws;
+        assert_eq!(
+            MultiAddr::from(Segment::Tcp(22)) + Segment::P2pCircuit,
+            addr.decapsulate_code(6));
+        assert_eq!(addr, addr.decapsulate_code(17));
+    }
+
+    #[test]
+    fn split_circuit_none() {
+        assert_eq!(None, MultiAddr::from(Segment::Tcp(22)).split_circuit());
+    }
 }
@@ -0,0 +1,337 @@
+//! Blocking connect and bind helpers for the transports supported by
+//! `std::net` and `std::os::unix::net`, i.e.
+//! `/(ip4|ip6)/<addr>/tcp/<port>`, `/(ip4|ip6)/<addr>/udp/<port>` and
+//! `/unix/<path>` (on unix platforms only).
+//!
+//! Asynchronous equivalents for tokio are in the `tokio` module when the
+//! `tokio` feature is enabled.
+//!
+//! A Unix socket that isn't bound to a path is unnamed and has no multiaddr,
+//! getting its address returns an error of kind `AddrNotAvailable`. Connecting
+//! sockets are usually unnamed, so `local_addr` fails on the connecting end
+//! and `peer_addr` fails on the accepting end.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::io::{ Read, Write };
+//! use maddr::net;
+//!
+//! let listener = net::bind(&"/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+//! let addr = listener.local_addr().unwrap();
+//! let mut client = net::connect(&addr).unwrap();
+//! let mut server = listener.accept().unwrap();
+//! assert_eq!(addr, client.peer_addr().unwrap());
+//!
+//! client.write_all(b"hello").unwrap();
+//! let mut buffer = [0; 5];
+//! server.read_exact(&mut buffer).unwrap();
+//! assert_eq!(b"hello", &buffer);
+//! ```
+
+use std::io;
+use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, UdpSocket };
+
+#[cfg(unix)]
+use std::os::unix::net::{ UnixListener, UnixStream };
+#[cfg(unix)]
+use std::path::Path;
+
+use { MultiAddr, Segment, Transport };
+
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+/// A connection made by `connect`, or accepted by a `Listener`.
+///
+/// Reading and writing a `Udp` stream receives and sends a single datagram
+/// per call.
+#[derive(Debug)]
+pub enum Stream {
+    /// A TCP connection.
+    Tcp(TcpStream),
+    /// A UDP socket connected to a single peer.
+    Udp(UdpSocket),
+    /// A Unix domain socket connection.
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// A socket bound by `bind`.
+///
+/// UDP sockets can't accept connections, use the `UdpSocket` directly to
+/// receive datagrams from any peer.
+#[derive(Debug)]
+pub enum Listener {
+    /// A TCP listener.
+    Tcp(TcpListener),
+    /// An unconnected UDP socket.
+    Udp(UdpSocket),
+    /// A Unix domain socket listener.
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+enum Target<'a> {
+    Ip(SocketAddr, Transport),
+    Unix(&'a str),
+}
+
+fn target<'a>(addr: &'a MultiAddr) -> io::Result<Target<'a>> {
+    if let [Segment::Unix(ref path)] = *addr.segments() {
+        return Ok(Target::Unix(path));
+    }
+    addr.to_transport_addr()
+        .map(|(socket_addr, transport)| Target::Ip(socket_addr, transport))
+        .map_err(|err| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a supported address: {}", addr, err)))
+}
+
+#[cfg(not(unix))]
+fn unix_unsupported(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("/unix{} is not supported on this platform", path))
+}
+
+#[cfg(unix)]
+fn unix_multiaddr(path: Option<&Path>) -> io::Result<MultiAddr> {
+    let path = try!(path.ok_or_else(|| io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "unnamed unix socket has no multiaddr")));
+    let path = try!(path.to_str().ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unix socket path {:?} is not utf-8", path))));
+    Ok(Segment::Unix(path.to_owned()).into())
+}
+
+/// Connect to the given address.
+///
+/// UDP sockets are bound to an unspecified address of the same family before
+/// being connected.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if the address is not one of the
+/// supported forms, otherwise any error from connecting.
+pub fn connect(addr: &MultiAddr) -> io::Result<Stream> {
+    match try!(target(addr)) {
+        Target::Ip(socket_addr, Transport::Tcp) => {
+            TcpStream::connect(socket_addr).map(Stream::Tcp)
+        }
+        Target::Ip(socket_addr, Transport::Udp) => {
+            let unspecified = match socket_addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            };
+            let socket = try!(UdpSocket::bind((unspecified, 0)));
+            try!(socket.connect(socket_addr));
+            Ok(Stream::Udp(socket))
+        }
+        #[cfg(unix)]
+        Target::Unix(path) => UnixStream::connect(path).map(Stream::Unix),
+        #[cfg(not(unix))]
+        Target::Unix(path) => Err(unix_unsupported(path)),
+    }
+}
+
+/// Bind to the given address, a port of 0 will bind an unused port, use
+/// `Listener::local_addr` to find out which.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if the address is not one of the
+/// supported forms, otherwise any error from binding.
+pub fn bind(addr: &MultiAddr) -> io::Result<Listener> {
+    match try!(target(addr)) {
+        Target::Ip(socket_addr, Transport::Tcp) => {
+            TcpListener::bind(socket_addr).map(Listener::Tcp)
+        }
+        Target::Ip(socket_addr, Transport::Udp) => {
+            UdpSocket::bind(socket_addr).map(Listener::Udp)
+        }
+        #[cfg(unix)]
+        Target::Unix(path) => UnixListener::bind(path).map(Listener::Unix),
+        #[cfg(not(unix))]
+        Target::Unix(path) => Err(unix_unsupported(path)),
+    }
+}
+
+impl Stream {
+    /// The local address of this stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `AddrNotAvailable` if this end is an unnamed
+    /// Unix socket, see the module documentation.
+    pub fn local_addr(&self) -> io::Result<MultiAddr> {
+        match *self {
+            Stream::Tcp(ref stream) => stream.local_addr().map(MultiAddr::from),
+            Stream::Udp(ref socket) => socket.local_addr()
+                .map(|addr| MultiAddr::from_transport_addr(addr, Transport::Udp)),
+            #[cfg(unix)]
+            Stream::Unix(ref stream) => stream.local_addr()
+                .and_then(|addr| unix_multiaddr(addr.as_pathname())),
+        }
+    }
+
+    /// The address of the remote end of this stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `AddrNotAvailable` if the remote end is an
+    /// unnamed Unix socket, see the module documentation.
+    pub fn peer_addr(&self) -> io::Result<MultiAddr> {
+        match *self {
+            Stream::Tcp(ref stream) => stream.peer_addr().map(MultiAddr::from),
+            Stream::Udp(ref socket) => socket.peer_addr()
+                .map(|addr| MultiAddr::from_transport_addr(addr, Transport::Udp)),
+            #[cfg(unix)]
+            Stream::Unix(ref stream) => stream.peer_addr()
+                .and_then(|addr| unix_multiaddr(addr.as_pathname())),
+        }
+    }
+}
+
+impl Listener {
+    /// The address this listener is bound to, with any unused port that was
+    /// chosen when binding port 0.
+    pub fn local_addr(&self) -> io::Result<MultiAddr> {
+        match *self {
+            Listener::Tcp(ref listener) => listener.local_addr().map(MultiAddr::from),
+            Listener::Udp(ref socket) => socket.local_addr()
+                .map(|addr| MultiAddr::from_transport_addr(addr, Transport::Udp)),
+            #[cfg(unix)]
+            Listener::Unix(ref listener) => listener.local_addr()
+                .and_then(|addr| unix_multiaddr(addr.as_pathname())),
+        }
+    }
+
+    /// Block until a new connection is made to this listener.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` for a UDP socket, otherwise any
+    /// error from accepting.
+    pub fn accept(&self) -> io::Result<Stream> {
+        match *self {
+            Listener::Tcp(ref listener) => listener.accept().map(|(stream, _)| Stream::Tcp(stream)),
+            Listener::Udp(_) => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "udp sockets can't accept connections")),
+            #[cfg(unix)]
+            Listener::Unix(ref listener) => listener.accept().map(|(stream, _)| Stream::Unix(stream)),
+        }
+    }
+}
+
+impl io::Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut stream) => stream.read(buf),
+            Stream::Udp(ref socket) => socket.recv(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+impl io::Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut stream) => stream.write(buf),
+            Stream::Udp(ref socket) => socket.send(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref mut stream) => stream.flush(),
+            Stream::Udp(_) => Ok(()),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ self, Read, Write };
+
+    use { MultiAddr, Segment };
+    use super::{ Listener, bind, connect };
+
+    fn round_trip(addr: &str) {
+        let listener = bind(&addr.parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut client = connect(&addr).unwrap();
+        let mut server = listener.accept().unwrap();
+        assert_eq!(addr, server.local_addr().unwrap());
+
+        client.write_all(b"ping").unwrap();
+        let mut buffer = [0; 4];
+        server.read_exact(&mut buffer).unwrap();
+        assert_eq!(b"ping", &buffer);
+
+        server.write_all(b"pong").unwrap();
+        drop(server);
+        let mut buffer = Vec::new();
+        let _ = client.read_to_end(&mut buffer).unwrap();
+        assert_eq!(b"pong", &buffer[..]);
+    }
+
+    #[test]
+    fn tcp4() {
+        round_trip("/ip4/127.0.0.1/tcp/0");
+    }
+
+    #[test]
+    fn tcp6() {
+        // Not all environments have IPv6 loopback available
+        if bind(&"/ip6/::1/tcp/0".parse().unwrap()).is_ok() {
+            round_trip("/ip6/::1/tcp/0");
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn unix() {
+        let path = ::std::env::temp_dir().join(format!("maddr-net-{}.sock", ::std::process::id()));
+        let _ = ::std::fs::remove_file(&path);
+        round_trip(&format!("/unix{}", path.display()));
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn udp() {
+        let listener = bind(&"/ip4/127.0.0.1/udp/0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let socket = match listener {
+            Listener::Udp(ref socket) => socket,
+            _ => panic!("expected a udp socket"),
+        };
+        assert_eq!(io::ErrorKind::InvalidInput, listener.accept().unwrap_err().kind());
+
+        let mut client = connect(&addr).unwrap();
+        assert_eq!(addr, client.peer_addr().unwrap());
+        client.write_all(b"ping").unwrap();
+        let mut buffer = [0; 8];
+        let (length, from) = socket.recv_from(&mut buffer).unwrap();
+        assert_eq!(b"ping", &buffer[..length]);
+
+        let _ = socket.send_to(b"pong", from).unwrap();
+        assert_eq!(4, client.read(&mut buffer).unwrap());
+        assert_eq!(b"pong", &buffer[..4]);
+    }
+
+    #[test]
+    fn unsupported() {
+        let addr = MultiAddr::from(Segment::Dns4("localhost".to_owned())) + Segment::Tcp(80);
+        assert_eq!(io::ErrorKind::InvalidInput, connect(&addr).unwrap_err().kind());
+        let addr: MultiAddr = "/ip4/127.0.0.1/tcp/0/ws".parse().unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, bind(&addr).unwrap_err().kind());
+    }
+}
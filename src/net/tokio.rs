@@ -0,0 +1,397 @@
+//! Tokio equivalents of the TCP and Unix domain socket types, bound and
+//! connected by `MultiAddr`.
+//!
+//! These must be used from within a tokio runtime with IO enabled.
+
+use std::fmt;
+use std::future::{ self, Future };
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+#[cfg(unix)]
+use std::path::PathBuf;
+
+use ::tokio::io::{ AsyncRead, AsyncWrite, ReadBuf };
+
+use { MultiAddr, Transport };
+use super::{ Target, target };
+#[cfg(unix)]
+use super::unix_multiaddr;
+
+/// A TCP listener bound to a `/(ip4|ip6)/<addr>/tcp/<port>` address.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate maddr;
+/// extern crate tokio;
+///
+/// use maddr::net::tokio::{ TcpListener, TcpStream };
+///
+/// # fn main() {
+/// let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+/// let _guard = runtime.enter();
+///
+/// let listener = TcpListener::bind(&"/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+/// let addr = listener.local_addr().unwrap();
+/// assert_ne!("/ip4/127.0.0.1/tcp/0", addr.to_string());
+///
+/// let client = runtime.block_on(TcpStream::connect(&addr)).unwrap();
+/// let server = runtime.block_on(listener.accept()).unwrap();
+/// assert_eq!(client.local_addr().unwrap(), server.peer_addr().unwrap());
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TcpListener {
+    inner: ::tokio::net::TcpListener,
+}
+
+/// A TCP connection made by `TcpStream::connect`, or accepted by a
+/// `TcpListener`.
+#[derive(Debug)]
+pub struct TcpStream {
+    inner: ::tokio::net::TcpStream,
+}
+
+/// A Unix domain socket listener bound to a `/unix/<path>` address.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixListener {
+    inner: ::tokio::net::UnixListener,
+}
+
+/// A Unix domain socket connection made by `UnixStream::connect`, or
+/// accepted by a `UnixListener`.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixStream {
+    inner: ::tokio::net::UnixStream,
+}
+
+/// The future returned by `TcpStream::connect` and `UnixStream::connect`.
+pub struct ConnectFuture<T> {
+    inner: Pin<Box<dyn Future<Output = io::Result<T>> + Send>>,
+}
+
+/// The future returned by `TcpListener::accept` and `UnixListener::accept`.
+#[derive(Debug)]
+pub struct AcceptFuture<'a, L: 'a> {
+    listener: &'a L,
+}
+
+/// Maps the output of a boxed tokio future into one of the wrapper types.
+struct MapOk<S, T> {
+    future: Pin<Box<dyn Future<Output = io::Result<S>> + Send>>,
+    f: fn(S) -> T,
+}
+
+fn tcp_addr(addr: &MultiAddr) -> io::Result<SocketAddr> {
+    match try!(target(addr)) {
+        Target::Ip(socket_addr, Transport::Tcp) => Ok(socket_addr),
+        _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a tcp address", addr))),
+    }
+}
+
+#[cfg(unix)]
+fn unix_path(addr: &MultiAddr) -> io::Result<PathBuf> {
+    match try!(target(addr)) {
+        Target::Unix(path) => Ok(PathBuf::from(path)),
+        _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a unix address", addr))),
+    }
+}
+
+impl TcpListener {
+    /// Bind a listener to the given address, a port of 0 will bind an unused
+    /// port, use `local_addr` to find out which.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the address is not a TCP
+    /// address, otherwise any error from binding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a tokio runtime.
+    pub fn bind(addr: &MultiAddr) -> io::Result<TcpListener> {
+        let listener = try!(::std::net::TcpListener::bind(try!(tcp_addr(addr))));
+        try!(listener.set_nonblocking(true));
+        let inner = try!(::tokio::net::TcpListener::from_std(listener));
+        Ok(TcpListener { inner: inner })
+    }
+
+    /// The address this listener is bound to, with any unused port that was
+    /// chosen when binding port 0.
+    pub fn local_addr(&self) -> io::Result<MultiAddr> {
+        self.inner.local_addr().map(MultiAddr::from)
+    }
+
+    /// Accept a new connection to this listener.
+    pub fn accept<'a>(&'a self) -> AcceptFuture<'a, TcpListener> {
+        AcceptFuture { listener: self }
+    }
+
+    /// Get the underlying tokio listener.
+    pub fn into_inner(self) -> ::tokio::net::TcpListener {
+        self.inner
+    }
+}
+
+impl TcpStream {
+    /// Connect to the given address.
+    ///
+    /// # Errors
+    ///
+    /// The future resolves to an error of kind `InvalidInput` if the address
+    /// is not a TCP address, otherwise any error from connecting.
+    pub fn connect(addr: &MultiAddr) -> ConnectFuture<TcpStream> {
+        match tcp_addr(addr) {
+            Ok(socket_addr) => ConnectFuture::map(
+                Box::pin(::tokio::net::TcpStream::connect(socket_addr)),
+                |inner| TcpStream { inner: inner }),
+            Err(err) => ConnectFuture::err(err),
+        }
+    }
+
+    /// The local address of this stream.
+    pub fn local_addr(&self) -> io::Result<MultiAddr> {
+        self.inner.local_addr().map(MultiAddr::from)
+    }
+
+    /// The address of the remote end of this stream.
+    pub fn peer_addr(&self) -> io::Result<MultiAddr> {
+        self.inner.peer_addr().map(MultiAddr::from)
+    }
+
+    /// Get the underlying tokio stream.
+    pub fn into_inner(self) -> ::tokio::net::TcpStream {
+        self.inner
+    }
+}
+
+#[cfg(unix)]
+impl UnixListener {
+    /// Bind a listener to the given `/unix/<path>` address.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the address is not a Unix
+    /// address, otherwise any error from binding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a tokio runtime.
+    pub fn bind(addr: &MultiAddr) -> io::Result<UnixListener> {
+        let inner = try!(::tokio::net::UnixListener::bind(try!(unix_path(addr))));
+        Ok(UnixListener { inner: inner })
+    }
+
+    /// The address this listener is bound to.
+    pub fn local_addr(&self) -> io::Result<MultiAddr> {
+        self.inner.local_addr().and_then(|addr| unix_multiaddr(addr.as_pathname()))
+    }
+
+    /// Accept a new connection to this listener.
+    pub fn accept<'a>(&'a self) -> AcceptFuture<'a, UnixListener> {
+        AcceptFuture { listener: self }
+    }
+
+    /// Get the underlying tokio listener.
+    pub fn into_inner(self) -> ::tokio::net::UnixListener {
+        self.inner
+    }
+}
+
+#[cfg(unix)]
+impl UnixStream {
+    /// Connect to the given `/unix/<path>` address.
+    ///
+    /// # Errors
+    ///
+    /// The future resolves to an error of kind `InvalidInput` if the address
+    /// is not a Unix address, otherwise any error from connecting.
+    pub fn connect(addr: &MultiAddr) -> ConnectFuture<UnixStream> {
+        match unix_path(addr) {
+            Ok(path) => ConnectFuture::map(
+                Box::pin(::tokio::net::UnixStream::connect(path)),
+                |inner| UnixStream { inner: inner }),
+            Err(err) => ConnectFuture::err(err),
+        }
+    }
+
+    /// The local address of this stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `AddrNotAvailable` if this end is unnamed, see
+    /// the `net` module documentation.
+    pub fn local_addr(&self) -> io::Result<MultiAddr> {
+        self.inner.local_addr().and_then(|addr| unix_multiaddr(addr.as_pathname()))
+    }
+
+    /// The address of the remote end of this stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `AddrNotAvailable` if the remote end is
+    /// unnamed, see the `net` module documentation.
+    pub fn peer_addr(&self) -> io::Result<MultiAddr> {
+        self.inner.peer_addr().and_then(|addr| unix_multiaddr(addr.as_pathname()))
+    }
+
+    /// Get the underlying tokio stream.
+    pub fn into_inner(self) -> ::tokio::net::UnixStream {
+        self.inner
+    }
+}
+
+impl<T: Send + 'static> ConnectFuture<T> {
+    fn map<S: 'static>(future: Pin<Box<dyn Future<Output = io::Result<S>> + Send>>, f: fn(S) -> T) -> ConnectFuture<T> {
+        ConnectFuture { inner: Box::pin(MapOk { future: future, f: f }) }
+    }
+
+    fn err(err: io::Error) -> ConnectFuture<T> {
+        ConnectFuture { inner: Box::pin(future::ready(Err(err))) }
+    }
+}
+
+impl<T> Future for ConnectFuture<T> {
+    type Output = io::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<T>> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl<T> fmt::Debug for ConnectFuture<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectFuture").finish_non_exhaustive()
+    }
+}
+
+impl<S, T> Future for MapOk<S, T> {
+    type Output = io::Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<T>> {
+        let f = self.f;
+        self.future.as_mut().poll(cx).map_ok(f)
+    }
+}
+
+impl<'a> Future for AcceptFuture<'a, TcpListener> {
+    type Output = io::Result<TcpStream>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<TcpStream>> {
+        self.listener.inner.poll_accept(cx).map_ok(|(inner, _)| TcpStream { inner: inner })
+    }
+}
+
+#[cfg(unix)]
+impl<'a> Future for AcceptFuture<'a, UnixListener> {
+    type Output = io::Result<UnixStream>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<UnixStream>> {
+        self.listener.inner.poll_accept(cx).map_ok(|(inner, _)| UnixStream { inner: inner })
+    }
+}
+
+macro_rules! delegate_async_io {
+    ($ty:ty) => {
+        impl AsyncRead for $ty {
+            fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.inner).poll_read(cx, buf)
+            }
+        }
+
+        impl AsyncWrite for $ty {
+            fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.inner).poll_write(cx, buf)
+            }
+
+            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.inner).poll_flush(cx)
+            }
+
+            fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.inner).poll_shutdown(cx)
+            }
+        }
+    }
+}
+
+delegate_async_io!(TcpStream);
+#[cfg(unix)]
+delegate_async_io!(UnixStream);
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use ::tokio::runtime::{ Builder, Runtime };
+
+    use MultiAddr;
+    use async_io::tokio::{ AsyncReadMultiAddr, AsyncWriteMultiAddr };
+    use super::{ TcpListener, TcpStream };
+
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().enable_io().build().unwrap()
+    }
+
+    #[test]
+    fn tcp() {
+        let runtime = runtime();
+        let _guard = runtime.enter();
+
+        let listener = TcpListener::bind(&"/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert_ne!(addr, "/ip4/127.0.0.1/tcp/0".parse().unwrap());
+
+        let mut client = runtime.block_on(TcpStream::connect(&addr)).unwrap();
+        let mut server = runtime.block_on(listener.accept()).unwrap();
+        assert_eq!(addr, client.peer_addr().unwrap());
+        assert_eq!(client.local_addr().unwrap(), server.peer_addr().unwrap());
+
+        runtime.block_on(client.write_multiaddr_length_prefixed(&addr)).unwrap();
+        assert_eq!(addr, runtime.block_on(server.read_multiaddr_length_prefixed(32)).unwrap());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn unix() {
+        use super::{ UnixListener, UnixStream };
+
+        let runtime = runtime();
+        let _guard = runtime.enter();
+
+        let path = ::std::env::temp_dir().join(format!("maddr-tokio-{}.sock", ::std::process::id()));
+        let _ = ::std::fs::remove_file(&path);
+        let addr: MultiAddr = format!("/unix{}", path.display()).parse().unwrap();
+
+        let listener = UnixListener::bind(&addr).unwrap();
+        assert_eq!(addr, listener.local_addr().unwrap());
+        let mut client = runtime.block_on(UnixStream::connect(&addr)).unwrap();
+        let mut server = runtime.block_on(listener.accept()).unwrap();
+        assert_eq!(addr, client.peer_addr().unwrap());
+
+        runtime.block_on(server.write_multiaddr_length_prefixed(&addr)).unwrap();
+        assert_eq!(addr, runtime.block_on(client.read_multiaddr_length_prefixed(256)).unwrap());
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn not_tcp() {
+        let runtime = runtime();
+        let _guard = runtime.enter();
+
+        let addr: MultiAddr = "/ip4/127.0.0.1/udp/0".parse().unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, TcpListener::bind(&addr).unwrap_err().kind());
+        assert_eq!(
+            io::ErrorKind::InvalidInput,
+            runtime.block_on(TcpStream::connect(&addr)).unwrap_err().kind());
+    }
+}
@@ -0,0 +1,317 @@
+use std::{ cmp, error, fmt, io };
+
+use MultiAddr;
+use decoder::{ Frame, Header, frame_header, header };
+use protocol::EMPTY;
+use read::read_segment_value;
+
+/// Options controlling how strictly a binary `MultiAddr` is decoded, for use
+/// with untrusted input.
+///
+/// The default options accept everything `ReadMultiAddr::read_multiaddr`
+/// does, `DecodeOptions::strict` returns options with reasonable limits and
+/// a canonical encoding required. Violations are returned as an `io::Error` of
+/// kind `InvalidData` wrapping a `DecodeError`. The same options can limit a
+/// `SegmentDecoder`, see `SegmentDecoder::with_options`.
+///
+/// # Examples
+///
+/// ```rust
+/// use maddr::{ DecodeError, DecodeOptions };
+///
+/// let options = DecodeOptions::strict().with_max_segments(1);
+/// let mut buffer: &[u8] = &[4, 1, 2, 3, 4, 6, 0, 22];
+/// let err = options.read_multiaddr(&mut buffer).unwrap_err();
+/// assert_eq!(
+///     Some(&DecodeError::TooManySegments { max_segments: 1 }),
+///     err.get_ref().and_then(|err| err.downcast_ref()));
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct DecodeOptions {
+    canonical_varints: bool,
+    max_length: usize,
+    max_segments: usize,
+    max_segment_length: usize,
+}
+
+/// A violation of the limits set by `DecodeOptions`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum DecodeError {
+    /// A varint was encoded with more bytes than necessary.
+    NonCanonicalVarint {
+        /// The byte offset of the varint.
+        offset: usize,
+    },
+
+    /// A segment used a deprecated code for its protocol, i.e. the legacy
+    /// udp code 17 rather than 273.
+    LegacyCode {
+        /// The byte offset of the segment.
+        offset: usize,
+        /// The deprecated code.
+        code: u64,
+    },
+
+    /// The encoded address was longer than the maximum length.
+    TooLong {
+        /// The maximum length in bytes.
+        max_length: usize,
+    },
+
+    /// The address had more than the maximum number of segments.
+    TooManySegments {
+        /// The maximum number of segments.
+        max_segments: usize,
+    },
+
+    /// A segment's value was longer than the maximum segment length.
+    SegmentTooLong {
+        /// The index of the segment.
+        index: usize,
+        /// The length of the segment's value in bytes.
+        length: usize,
+        /// The maximum length of a segment's value in bytes.
+        max_segment_length: usize,
+    },
+}
+
+impl DecodeOptions {
+    /// Create options that accept any valid address, as
+    /// `ReadMultiAddr::read_multiaddr` does.
+    pub fn new() -> DecodeOptions {
+        DecodeOptions {
+            canonical_varints: false,
+            max_length: usize::max_value(),
+            max_segments: usize::max_value(),
+            max_segment_length: usize::max_value(),
+        }
+    }
+
+    /// Create options requiring a canonical encoding and limiting addresses to
+    /// 1024 bytes, 32 segments and 512 byte segment values, which is enough
+    /// for any address in the protocol table.
+    pub fn strict() -> DecodeOptions {
+        DecodeOptions {
+            canonical_varints: true,
+            max_length: 1024,
+            max_segments: 32,
+            max_segment_length: 512,
+        }
+    }
+
+    /// Set whether the encoding must be canonical, i.e. varints are encoded in
+    /// as few bytes as possible and no segment uses a legacy code.
+    pub fn with_canonical_varints(self, canonical_varints: bool) -> DecodeOptions {
+        DecodeOptions { canonical_varints: canonical_varints, ..self }
+    }
+
+    /// Set the maximum length in bytes of an encoded address.
+    pub fn with_max_length(self, max_length: usize) -> DecodeOptions {
+        DecodeOptions { max_length: max_length, ..self }
+    }
+
+    /// Set the maximum number of segments in an address.
+    pub fn with_max_segments(self, max_segments: usize) -> DecodeOptions {
+        DecodeOptions { max_segments: max_segments, ..self }
+    }
+
+    /// Set the maximum length in bytes of a segment's value, excluding any
+    /// length prefix.
+    pub fn with_max_segment_length(self, max_segment_length: usize) -> DecodeOptions {
+        DecodeOptions { max_segment_length: max_segment_length, ..self }
+    }
+
+    /// Read a `MultiAddr` from the given stream, consuming the entire stream
+    /// as `ReadMultiAddr::read_multiaddr` does, but reading no more than one
+    /// byte past the maximum length.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` wrapping a `DecodeError` if the
+    /// address violates these options, otherwise as for
+    /// `ReadMultiAddr::read_multiaddr`.
+    pub fn read_multiaddr<R: io::Read>(&self, reader: &mut R) -> io::Result<MultiAddr> {
+        let mut buffer = Vec::new();
+        let limit = (self.max_length as u64).saturating_add(1);
+        let _ = try!(io::Read::read_to_end(&mut io::Read::take(reader, limit), &mut buffer));
+        if buffer.len() > self.max_length {
+            return Err(DecodeError::TooLong { max_length: self.max_length }.into());
+        }
+
+        let mut segments = Vec::new();
+        let mut offset = 0;
+        while offset < buffer.len() {
+            let bytes = &buffer[offset..];
+            let truncated = || io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated segment");
+            let header = match try!(header(bytes, &EMPTY)) {
+                Some(header) => header,
+                None => return Err(truncated()),
+            };
+            try!(self.check_header(bytes, &header, offset, segments.len()));
+            let (code, value, end) = match try!(frame_header(bytes, &header)) {
+                Frame::Complete { code, value, end, .. } => (code, value, end),
+                Frame::Incomplete(_) => return Err(truncated()),
+            };
+            segments.push(try!(read_segment_value(code, &bytes[value..end], &EMPTY)));
+            offset += end;
+        }
+        Ok(MultiAddr::new(segments))
+    }
+
+    /// Check the header of the segment at the given byte offset and index
+    /// against these options, before its value is necessarily available.
+    pub(crate) fn check_header(&self, bytes: &[u8], header: &Header, offset: usize, index: usize) -> Result<(), DecodeError> {
+        let Header { code, value, data, length } = *header;
+        if index >= self.max_segments {
+            return Err(DecodeError::TooManySegments { max_segments: self.max_segments });
+        }
+        if self.canonical_varints {
+            // A varint longer than a single byte is only canonical if its
+            // last byte contributes to the value
+            if value > 1 && bytes[value - 1] == 0 {
+                return Err(DecodeError::NonCanonicalVarint { offset: offset });
+            }
+            if data - value > 1 && bytes[data - 1] == 0 {
+                return Err(DecodeError::NonCanonicalVarint { offset: offset + value });
+            }
+            if code == 17 {
+                return Err(DecodeError::LegacyCode { offset: offset, code: code });
+            }
+        }
+        if length > self.max_segment_length as u64 {
+            return Err(DecodeError::SegmentTooLong {
+                index: index,
+                length: cmp::min(length, usize::max_value() as u64) as usize,
+                max_segment_length: self.max_segment_length,
+            });
+        }
+        if ((offset + data) as u64).saturating_add(length) > self.max_length as u64 {
+            return Err(DecodeError::TooLong { max_length: self.max_length });
+        }
+        Ok(())
+    }
+}
+
+impl Default for DecodeOptions {
+    fn default() -> DecodeOptions {
+        DecodeOptions::new()
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::NonCanonicalVarint { offset } =>
+                write!(f, "non-canonical varint at byte {}", offset),
+            DecodeError::LegacyCode { offset, code } =>
+                write!(f, "legacy protocol code {} at byte {}", code, offset),
+            DecodeError::TooLong { max_length } =>
+                write!(f, "multiaddr longer than {} bytes", max_length),
+            DecodeError::TooManySegments { max_segments } =>
+                write!(f, "multiaddr has more than {} segments", max_segments),
+            DecodeError::SegmentTooLong { index, length, max_segment_length } =>
+                write!(f, "segment {} is {} bytes, longer than {} bytes",
+                    index, length, max_segment_length),
+        }
+    }
+}
+
+impl error::Error for DecodeError {
+    fn description(&self) -> &str {
+        match *self {
+            DecodeError::NonCanonicalVarint { .. } => "non-canonical varint",
+            DecodeError::LegacyCode { .. } => "legacy protocol code",
+            DecodeError::TooLong { .. } => "multiaddr too long",
+            DecodeError::TooManySegments { .. } => "too many segments",
+            DecodeError::SegmentTooLong { .. } => "segment too long",
+        }
+    }
+}
+
+impl From<DecodeError> for io::Error {
+    fn from(err: DecodeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use { DecodeError, DecodeOptions, MultiAddr, Segment };
+
+    fn decode_error(options: DecodeOptions, mut bytes: &[u8]) -> DecodeError {
+        let err = options.read_multiaddr(&mut bytes).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        *err.into_inner().unwrap().downcast().unwrap()
+    }
+
+    #[test]
+    fn permissive() {
+        let mut bytes: &[u8] = &[0x86, 0x00, 0, 22, 53, 0x81, 0x00, b'a'];
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Tcp(22), Segment::Dns("a".to_owned())]),
+            DecodeOptions::new().read_multiaddr(&mut bytes).unwrap());
+    }
+
+    #[test]
+    fn strict() {
+        let mut bytes: &[u8] = &[6, 0, 22, 53, 1, b'a', 0x91, 0x02, 0, 53];
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::Tcp(22),
+                Segment::Dns("a".to_owned()),
+                Segment::Udp(53),
+            ]),
+            DecodeOptions::strict().read_multiaddr(&mut bytes).unwrap());
+    }
+
+    #[test]
+    fn non_canonical_code() {
+        assert_eq!(
+            DecodeError::NonCanonicalVarint { offset: 3 },
+            decode_error(DecodeOptions::strict(), &[6, 0, 22, 0x86, 0x00, 0, 22]));
+    }
+
+    #[test]
+    fn non_canonical_length() {
+        assert_eq!(
+            DecodeError::NonCanonicalVarint { offset: 1 },
+            decode_error(DecodeOptions::strict(), &[53, 0x81, 0x00, b'a']));
+    }
+
+    #[test]
+    fn legacy_code() {
+        let bytes = [6, 0, 22, 17, 0, 53];
+        assert_eq!(
+            DecodeError::LegacyCode { offset: 3, code: 17 },
+            decode_error(DecodeOptions::strict(), &bytes));
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Tcp(22), Segment::Udp(53)]),
+            DecodeOptions::new().read_multiaddr(&mut &bytes[..]).unwrap());
+    }
+
+    #[test]
+    fn too_long() {
+        let options = DecodeOptions::new().with_max_length(4);
+        assert_eq!(
+            DecodeError::TooLong { max_length: 4 },
+            decode_error(options, &[4, 1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn too_many_segments() {
+        let options = DecodeOptions::new().with_max_segments(2);
+        assert_eq!(
+            DecodeError::TooManySegments { max_segments: 2 },
+            decode_error(options, &[0xe0, 0x03, 0xe0, 0x03, 0xe0, 0x03]));
+    }
+
+    #[test]
+    fn segment_too_long() {
+        let options = DecodeOptions::new().with_max_segment_length(2);
+        assert_eq!(
+            DecodeError::SegmentTooLong { index: 1, length: 3, max_segment_length: 2 },
+            decode_error(options, &[6, 0, 22, 53, 3, b'a', b'b', b'c']));
+    }
+}
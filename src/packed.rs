@@ -0,0 +1,326 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{ Hash, Hasher };
+use std::io;
+use std::sync::Arc;
+
+use { MultiAddr, MultiAddrRef, Registry, Segment, WriteMultiAddr };
+use decoder::protocol_info;
+use protocol::EMPTY;
+use read::read_next_segment;
+
+/// A multiaddr stored in its canonical binary form.
+///
+/// Cloning only bumps a reference count, and equality, hashing and ordering
+/// work directly on the encoded bytes, making this suitable for storing large
+/// numbers of addresses. Segments are decoded on demand while iterating.
+///
+/// Addresses using registered protocols keep a reference to the `Registry`
+/// so their segments can be decoded, the registry is not considered when
+/// comparing addresses.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::convert::TryFrom;
+/// use maddr::{ MultiAddr, PackedMultiAddr, Segment };
+///
+/// let addr: MultiAddr = "/ip4/1.2.3.4/tcp/22".parse().unwrap();
+/// let packed = PackedMultiAddr::try_from(&addr).unwrap();
+///
+/// assert_eq!(&[4, 1, 2, 3, 4, 6, 0, 22], packed.as_bytes());
+/// assert_eq!(Some(Segment::Tcp(22)), packed.segments().nth(1));
+/// assert_eq!(addr, packed.to_multiaddr());
+/// ```
+#[derive(Clone)]
+pub struct PackedMultiAddr {
+    bytes: Arc<[u8]>,
+    registry: Option<Arc<Registry>>,
+}
+
+/// An iterator over the segments of a `PackedMultiAddr`, decoding each one as
+/// it is reached.
+#[derive(Debug)]
+pub struct PackedSegments<'a> {
+    bytes: &'a [u8],
+    registry: &'a Registry,
+}
+
+impl PackedMultiAddr {
+    /// Validate and store the given binary encoded multiaddr.
+    ///
+    /// The bytes are re-encoded before being stored so that equal addresses
+    /// always have equal bytes, e.g. a legacy udp code is replaced with the
+    /// current one.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error encountered decoding the address, see
+    /// `ReadMultiAddr::read_multiaddr`.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<PackedMultiAddr> {
+        PackedMultiAddr::read(bytes, None)
+    }
+
+    /// Validate and store the given binary encoded multiaddr, supporting the
+    /// protocols in the registry in addition to the built in protocols, as
+    /// for `from_bytes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error encountered decoding the address, see
+    /// `Registry::read_multiaddr`.
+    pub fn from_bytes_with_registry(bytes: &[u8], registry: Arc<Registry>) -> io::Result<PackedMultiAddr> {
+        PackedMultiAddr::read(bytes, Some(registry))
+    }
+
+    /// Encode and store the given address, supporting the protocols in the
+    /// registry in addition to the built in protocols, as for
+    /// `PackedMultiAddr::try_from`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the address can't be
+    /// decoded from its encoding unchanged.
+    pub fn from_multiaddr_with_registry(multiaddr: &MultiAddr, registry: Arc<Registry>) -> io::Result<PackedMultiAddr> {
+        PackedMultiAddr::pack(multiaddr, Some(registry))
+    }
+
+    fn read(mut bytes: &[u8], registry: Option<Arc<Registry>>) -> io::Result<PackedMultiAddr> {
+        let multiaddr = try!(registry.as_ref().map_or(&EMPTY, |registry| registry).read_multiaddr(&mut bytes));
+        Ok(PackedMultiAddr::encode(&multiaddr, registry))
+    }
+
+    fn pack(multiaddr: &MultiAddr, registry: Option<Arc<Registry>>) -> io::Result<PackedMultiAddr> {
+        let invalid = |message| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        for segment in multiaddr.segments() {
+            if let Segment::Unknown(code, _) = *segment {
+                if protocol_info(code).is_some() {
+                    return invalid(format!("Unknown segment uses the built in code {}", code));
+                }
+            }
+        }
+        let packed = PackedMultiAddr::encode(multiaddr, registry);
+        // A `MultiAddr` can hold segments without a readable encoding, e.g. a
+        // `Segment::Unknown` with an unregistered code, or a value that isn't
+        // the size its protocol requires
+        let mut bytes = packed.as_bytes();
+        match packed.registry().read_multiaddr(&mut bytes) {
+            Ok(ref decoded) if decoded == multiaddr => Ok(packed),
+            Ok(_) => invalid("Multiaddr changes when decoded from its encoding".to_owned()),
+            Err(err) => invalid(format!("Multiaddr can't be decoded from its encoding: {}", err)),
+        }
+    }
+
+    fn encode(multiaddr: &MultiAddr, registry: Option<Arc<Registry>>) -> PackedMultiAddr {
+        let mut bytes = Vec::new();
+        bytes.write_multiaddr(multiaddr)
+            .expect("writing to a Vec cannot fail");
+        PackedMultiAddr { bytes: Arc::from(bytes), registry: registry }
+    }
+
+    fn registry(&self) -> &Registry {
+        self.registry.as_ref().map_or(&EMPTY, |registry| registry)
+    }
+
+    /// Get the canonical binary encoding of this address.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Borrow this address as a `MultiAddrRef`, without revalidating it.
+    pub fn as_multiaddr_ref<'a>(&'a self) -> MultiAddrRef<'a> {
+        MultiAddrRef::from_validated(&self.bytes, self.registry())
+    }
+
+    /// Iterate over the segments of this address, decoding them lazily.
+    pub fn segments<'a>(&'a self) -> PackedSegments<'a> {
+        PackedSegments { bytes: &self.bytes, registry: self.registry() }
+    }
+
+    /// Decode this address into a `MultiAddr`.
+    pub fn to_multiaddr(&self) -> MultiAddr {
+        MultiAddr::new(self.segments().collect())
+    }
+}
+
+impl<'a> Iterator for PackedSegments<'a> {
+    type Item = Segment;
+
+    fn next(&mut self) -> Option<Segment> {
+        read_next_segment(&mut self.bytes, self.registry)
+            .expect("packed multiaddr was validated on construction")
+    }
+}
+
+/// Encodes the address and checks that decoding the encoding gives back the
+/// same address, supporting only the built in protocols, see
+/// `PackedMultiAddr::from_multiaddr_with_registry`.
+///
+/// Fails with an error of kind `InvalidInput` for addresses that can't be
+/// represented, e.g. containing a `Segment::Unknown` with the code of a built
+/// in protocol or an unregistered code.
+impl<'a> TryFrom<&'a MultiAddr> for PackedMultiAddr {
+    type Error = io::Error;
+
+    fn try_from(multiaddr: &'a MultiAddr) -> io::Result<PackedMultiAddr> {
+        PackedMultiAddr::pack(multiaddr, None)
+    }
+}
+
+impl TryFrom<MultiAddr> for PackedMultiAddr {
+    type Error = io::Error;
+
+    fn try_from(multiaddr: MultiAddr) -> io::Result<PackedMultiAddr> {
+        PackedMultiAddr::try_from(&multiaddr)
+    }
+}
+
+impl<'a> From<&'a PackedMultiAddr> for MultiAddr {
+    fn from(packed: &'a PackedMultiAddr) -> MultiAddr {
+        packed.to_multiaddr()
+    }
+}
+
+impl From<PackedMultiAddr> for MultiAddr {
+    fn from(packed: PackedMultiAddr) -> MultiAddr {
+        packed.to_multiaddr()
+    }
+}
+
+impl PartialEq for PackedMultiAddr {
+    fn eq(&self, other: &PackedMultiAddr) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl Eq for PackedMultiAddr {}
+
+impl Hash for PackedMultiAddr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bytes.hash(state)
+    }
+}
+
+impl PartialOrd for PackedMultiAddr {
+    fn partial_cmp(&self, other: &PackedMultiAddr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PackedMultiAddr {
+    fn cmp(&self, other: &PackedMultiAddr) -> Ordering {
+        self.bytes.cmp(&other.bytes)
+    }
+}
+
+impl fmt::Display for PackedMultiAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.as_multiaddr_ref(), f)
+    }
+}
+
+impl fmt::Debug for PackedMultiAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(f.write_str("PackedMultiAddr(\""));
+        try!(fmt::Display::fmt(self, f));
+        try!(f.write_str("\")"));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ error, fmt, str };
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+
+    use { MultiAddr, PackedMultiAddr, Protocol, Registry, Segment, Size };
+
+    #[derive(Debug)]
+    struct Label;
+
+    impl Protocol for Label {
+        fn code(&self) -> u64 { 0x300001 }
+        fn name(&self) -> &str { "label" }
+        fn size(&self) -> Size { Size::LengthPrefixed }
+        fn parse_value(&self, value: &str) -> Result<Vec<u8>, Box<dyn error::Error + Send + Sync>> {
+            Ok(value.as_bytes().to_vec())
+        }
+        fn fmt_value(&self, value: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(try!(str::from_utf8(value).map_err(|_| fmt::Error)))
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let addr: MultiAddr = "/dns4/example.com/tcp/443/wss/p2p-circuit".parse().unwrap();
+        let packed = PackedMultiAddr::try_from(&addr).unwrap();
+        assert_eq!(addr, packed.to_multiaddr());
+        assert_eq!(addr.segments(), &packed.segments().collect::<Vec<_>>()[..]);
+        assert_eq!(addr.to_string(), packed.to_string());
+        assert_eq!(packed, PackedMultiAddr::from_bytes(packed.as_bytes()).unwrap());
+        assert_eq!(addr, packed.as_multiaddr_ref().to_owned());
+    }
+
+    #[test]
+    fn clone_shares_bytes() {
+        let packed = PackedMultiAddr::try_from(MultiAddr::from(Segment::Tcp(22))).unwrap();
+        let clone = packed.clone();
+        assert!(Arc::ptr_eq(&packed.bytes, &clone.bytes));
+    }
+
+    #[test]
+    fn compares_bytes() {
+        let a = PackedMultiAddr::from_bytes(&[6, 0, 1]).unwrap();
+        let b = PackedMultiAddr::from_bytes(&[6, 0, 2]).unwrap();
+        assert!(a < b);
+        let set: HashSet<_> = vec![a.clone(), b, a].into_iter().collect();
+        assert_eq!(2, set.len());
+    }
+
+    #[test]
+    fn canonicalizes() {
+        // legacy udp code 17 is stored as 273
+        let packed = PackedMultiAddr::from_bytes(&[17, 0, 1]).unwrap();
+        assert_eq!(&[0x91, 0x02, 0, 1], packed.as_bytes());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(PackedMultiAddr::from_bytes(&[4, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn unencodable() {
+        let invalid = vec![
+            MultiAddr::from(Segment::Garlic32(vec![1, 2, 3])),
+            MultiAddr::from(Segment::Ip6zone(String::new())),
+            MultiAddr::from(Segment::Unknown(4, vec![1])),
+            MultiAddr::from(Segment::Unknown(999, vec![1])) + Segment::Tcp(22),
+            // would be decoded as `Segment::Tcp(22)`
+            MultiAddr::from(Segment::Unknown(6, vec![0, 22])),
+            MultiAddr::from(Segment::Unknown(17, vec![0, 22])),
+            // value doesn't match its length prefix
+            MultiAddr::from(Segment::Unknown(0x300001, vec![3, b'f'])),
+        ];
+        for addr in invalid {
+            assert!(PackedMultiAddr::try_from(&addr).is_err(), "{:?}", addr);
+        }
+    }
+    #[test]
+    fn registry() {
+        let mut registry = Registry::new();
+        registry.register(Label).unwrap();
+        let registry = Arc::new(registry);
+        let addr = registry.parse("/label/foo/tcp/4001").unwrap();
+        assert!(PackedMultiAddr::try_from(&addr).is_err());
+
+        let packed = PackedMultiAddr::from_multiaddr_with_registry(&addr, registry.clone()).unwrap();
+        assert_eq!(addr, packed.to_multiaddr());
+        assert_eq!("/label/foo/tcp/4001", packed.to_string());
+        assert!(PackedMultiAddr::from_bytes(packed.as_bytes()).is_err());
+        assert_eq!(packed, PackedMultiAddr::from_bytes_with_registry(packed.as_bytes(), registry).unwrap());
+    }
+}
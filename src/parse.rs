@@ -1,59 +1,518 @@
+use std::{ error, fmt };
 use std::str::FromStr;
+use std::vec;
 
-use { Segment, MultiAddr };
+use data_encoding::{ BASE32_NOPAD, BASE64, HEXLOWER_PERMISSIVE };
+use mhash::{ MultiHash, ReadMultiHash };
+use multibase;
+
+use { Segment, MultiAddr, Protocol, Registry, Size };
+use decoder::protocol_info;
+use protocols;
+use protocol::join_value;
 use Segment::*;
-pub use self::error::*;
 
-mod error {
-    use std::{ io, num, net };
+type Cause = Box<dyn error::Error + Send + Sync>;
+
+/// The `/` separated parts of a multiaddr string, each with its byte offset.
+type Parts<'a> = vec::IntoIter<(usize, &'a str)>;
+
+/// An error encountered parsing the string representation of a `MultiAddr`.
+///
+/// Errors relating to a specific segment include the byte offset of the
+/// part of the string in error, and the index of the segment.
+///
+/// # Examples
+///
+/// ```rust
+/// use maddr::{ MultiAddr, ParseError };
+///
+/// match "/ip4/1.2.3.4/tcp/http".parse::<MultiAddr>() {
+///     Err(ParseError::InvalidValue { ref protocol, offset, index, .. }) => {
+///         assert_eq!((protocol.as_str(), offset, index), ("tcp", 17, 1));
+///     }
+///     other => panic!("unexpected result {:?}", other),
+/// }
+/// ```
+#[derive(Debug)]
+pub enum ParseError {
+    /// The string was empty, or only a `/`.
+    Empty,
+
+    /// The string didn't start with a `/`.
+    MissingLeadingSlash,
+
+    /// The string had a `/` following the last segment.
+    TrailingSlash {
+        /// The byte offset of the trailing `/`.
+        offset: usize,
+        /// The number of segments preceding the trailing `/`.
+        index: usize,
+    },
+
+    /// A segment used a protocol name that isn't built in or registered.
+    UnknownProtocol {
+        /// The unknown protocol name.
+        name: String,
+        /// The byte offset of the protocol name.
+        offset: usize,
+        /// The index of the segment.
+        index: usize,
+    },
+
+    /// An `x-<code>` segment used the code of a built in protocol, which must
+    /// be written using the protocol's name instead.
+    BuiltInCode {
+        /// The code.
+        code: u64,
+        /// The byte offset of the `x-<code>` name.
+        offset: usize,
+        /// The index of the segment.
+        index: usize,
+    },
+
+    /// A segment was missing the value its protocol requires.
+    MissingValue {
+        /// The name of the segment's protocol.
+        protocol: String,
+        /// The byte offset of the protocol name.
+        offset: usize,
+        /// The index of the segment.
+        index: usize,
+    },
+
+    /// A segment's value couldn't be parsed.
+    InvalidValue {
+        /// The name of the segment's protocol.
+        protocol: String,
+        /// The invalid value.
+        value: String,
+        /// The byte offset of the value.
+        offset: usize,
+        /// The index of the segment.
+        index: usize,
+        /// The error encountered parsing the value, either a `ValueError` or
+        /// the error from parsing a component such as a number or address.
+        cause: Box<dyn error::Error + Send + Sync>,
+    },
+}
+
+/// Ways a segment's value can be invalid, other than failing to parse a
+/// component such as a number or address.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ValueError {
+    /// The address was too short, containing this many characters.
+    AddressTooShort(usize),
+
+    /// The address was too long, containing this many characters.
+    AddressTooLong(usize),
+
+    /// The address was missing a port.
+    MissingPort,
+
+    /// The address had port 0.
+    InvalidPort,
 
-    use mhash;
+    /// The value was empty.
+    Empty,
 
-    error_chain! {
-        foreign_links {
-            MultiHash(mhash::error::parse::Error);
-            Num(num::ParseIntError);
-            Addr(net::AddrParseError);
-            Io(io::Error);
+    /// The value contained a `%` not followed by two hex digits.
+    InvalidPercentEscape,
+
+    /// The value's percent escapes decoded to invalid UTF-8.
+    InvalidUtf8,
+
+    /// The value had extra bytes following a multihash.
+    TrailingBytes,
+}
+
+impl ParseError {
+    /// Get the byte offset into the string at which this error occurred.
+    pub fn offset(&self) -> usize {
+        match *self {
+            ParseError::Empty | ParseError::MissingLeadingSlash => 0,
+            ParseError::TrailingSlash { offset, .. }
+                | ParseError::UnknownProtocol { offset, .. }
+                | ParseError::BuiltInCode { offset, .. }
+                | ParseError::MissingValue { offset, .. }
+                | ParseError::InvalidValue { offset, .. } => offset,
+        }
+    }
+
+    /// Get the index of the segment at which this error occurred.
+    pub fn index(&self) -> usize {
+        match *self {
+            ParseError::Empty | ParseError::MissingLeadingSlash => 0,
+            ParseError::TrailingSlash { index, .. }
+                | ParseError::UnknownProtocol { index, .. }
+                | ParseError::BuiltInCode { index, .. }
+                | ParseError::MissingValue { index, .. }
+                | ParseError::InvalidValue { index, .. } => index,
         }
     }
 }
 
-fn segment_from_strs<'a, S: Iterator<Item=&'a str>>(strs: &mut S) -> Result<Option<Segment>> {
-    let missing_data = Error::from("missing segment data");
-    if let Some(s) = strs.next() {
-        let data = || strs.next().ok_or(missing_data);
-        Ok(Some(match s {
-            "ip4" => IP4(try!(try!(data()).parse())),
-            "ip6" => IP6(try!(try!(data()).parse())),
-            "udp" => Udp(try!(try!(data()).parse())),
-            "dccp" => Dccp(try!(try!(data()).parse())),
-            "sctp" => Sctp(try!(try!(data()).parse())),
-            "tcp" => Tcp(try!(try!(data()).parse())),
-            "ipfs" => Ipfs(try!(try!(data()).parse())),
-            "udt" => Udt,
-            "utp" => Utp,
-            "http" => Http,
-            "https" => Https,
-            _ => { return Err(format!("unrecognised segment type {}", s).into()) }
-        }))
-    } else {
-        Ok(None)
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Empty =>
+                f.write_str("empty multiaddr"),
+            ParseError::MissingLeadingSlash =>
+                f.write_str("multiaddr must start with /"),
+            ParseError::TrailingSlash { offset, .. } =>
+                write!(f, "trailing / at byte {}", offset),
+            ParseError::UnknownProtocol { ref name, offset, index } =>
+                write!(f, "unknown protocol {:?} at byte {} (segment {})", name, offset, index),
+            ParseError::BuiltInCode { code, offset, index } =>
+                write!(f, "x-{} at byte {} (segment {}) is a built in protocol", code, offset, index),
+            ParseError::MissingValue { ref protocol, offset, index } =>
+                write!(f, "missing {} value at byte {} (segment {})", protocol, offset, index),
+            ParseError::InvalidValue { ref protocol, ref value, offset, index, ref cause } =>
+                write!(f, "invalid {} value {:?} at byte {} (segment {}): {}",
+                    protocol, value, offset, index, cause),
+        }
     }
 }
 
-impl FromStr for MultiAddr {
-    type Err = Error;
-    fn from_str(s: &str) -> Result<Self> {
-        if &s[0..1] != "/" {
-            return Err("didn't start with /".into());
+impl error::Error for ParseError {
+    fn description(&self) -> &str {
+        match *self {
+            ParseError::Empty => "empty multiaddr",
+            ParseError::MissingLeadingSlash => "missing leading slash",
+            ParseError::TrailingSlash { .. } => "trailing slash",
+            ParseError::UnknownProtocol { .. } => "unknown protocol",
+            ParseError::BuiltInCode { .. } => "built in protocol code",
+            ParseError::MissingValue { .. } => "missing value",
+            ParseError::InvalidValue { .. } => "invalid value",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ParseError::InvalidValue { ref cause, .. } => Some(&**cause),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValueError::AddressTooShort(length) =>
+                write!(f, "address too short, found {} characters", length),
+            ValueError::AddressTooLong(length) =>
+                write!(f, "address too long, found {} characters", length),
+            ValueError::MissingPort => f.write_str("address is missing a port"),
+            ValueError::InvalidPort => f.write_str("address has invalid port 0"),
+            ValueError::Empty => f.write_str("value is empty"),
+            ValueError::InvalidPercentEscape => f.write_str("invalid percent escape"),
+            ValueError::InvalidUtf8 => f.write_str("percent escapes are not valid utf-8"),
+            ValueError::TrailingBytes => f.write_str("unexpected bytes after multihash"),
+        }
+    }
+}
+
+impl error::Error for ValueError {
+    fn description(&self) -> &str {
+        match *self {
+            ValueError::AddressTooShort(_) => "address too short",
+            ValueError::AddressTooLong(_) => "address too long",
+            ValueError::MissingPort => "missing port",
+            ValueError::InvalidPort => "invalid port",
+            ValueError::Empty => "empty value",
+            ValueError::InvalidPercentEscape => "invalid percent escape",
+            ValueError::InvalidUtf8 => "invalid utf-8",
+            ValueError::TrailingBytes => "trailing bytes",
+        }
+    }
+}
+
+fn percent_decode(s: &str) -> Result<String, Cause> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            // from_str_radix accepts a leading sign, so check the digits
+            // explicitly
+            let escaped = try!(tail.get(0..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| ::std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or(ValueError::InvalidPercentEscape));
+            bytes.push(escaped);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Ok(try!(String::from_utf8(bytes).map_err(|_| ValueError::InvalidUtf8)))
+}
+
+fn multihash_from_str(s: &str) -> Result<MultiHash, Cause> {
+    s.parse().map_err(|err: <MultiHash as FromStr>::Err| err.to_string().into())
+}
+
+fn multihash_from_multibase(s: &str) -> Result<MultiHash, Cause> {
+    let (_, bytes) = try!(multibase::decode(s));
+    let mut bytes = &bytes[..];
+    let multihash = try!(bytes.read_multihash());
+    if !bytes.is_empty() {
+        return Err(ValueError::TrailingBytes.into());
+    }
+    Ok(multihash)
+}
+
+fn onion_from_str(s: &str, length: usize) -> Result<(Vec<u8>, u16), Cause> {
+    let mut parts = s.splitn(2, ':');
+    let addr = parts.next().unwrap_or("");
+    let port = try!(parts.next().ok_or(ValueError::MissingPort));
+    if addr.len() < length {
+        return Err(ValueError::AddressTooShort(addr.len()).into());
+    }
+    if addr.len() > length {
+        return Err(ValueError::AddressTooLong(addr.len()).into());
+    }
+    let hash = try!(BASE32_NOPAD.decode(addr.to_ascii_uppercase().as_bytes()));
+    let port = try!(port.parse());
+    if port == 0 {
+        return Err(ValueError::InvalidPort.into());
+    }
+    Ok((hash, port))
+}
+
+fn onion_from_str_v2(s: &str) -> Result<Segment, Cause> {
+    let (bytes, port) = try!(onion_from_str(s, 16));
+    let mut hash = [0; 10];
+    hash.copy_from_slice(&bytes);
+    Ok(Onion(hash, port))
+}
+
+fn onion_from_str_v3(s: &str) -> Result<Segment, Cause> {
+    let (bytes, port) = try!(onion_from_str(s, 56));
+    let mut key = [0; 35];
+    key.copy_from_slice(&bytes);
+    Ok(Onion3(key, port))
+}
+
+fn garlic64_from_str(s: &str) -> Result<Vec<u8>, Cause> {
+    if s.len() < 516 {
+        return Err(ValueError::AddressTooShort(s.len()).into());
+    }
+    if s.len() > 616 {
+        return Err(ValueError::AddressTooLong(s.len()).into());
+    }
+    // I2P uses a base64 alphabet with `-~` in place of `+/`
+    let s = s.replace('-', "+").replace('~', "/");
+    Ok(try!(BASE64.decode(s.as_bytes())))
+}
+
+fn garlic32_from_str(s: &str) -> Result<Vec<u8>, Cause> {
+    // Either the 32 byte hash of a destination, or an encrypted lease set of
+    // at least 35 bytes, check the decoded length to match the binary format
+    let bytes = try!(BASE32_NOPAD.decode(s.to_ascii_uppercase().as_bytes()));
+    if bytes.len() != 32 && bytes.len() < 35 {
+        return Err(ValueError::AddressTooShort(s.len()).into());
+    }
+    Ok(bytes)
+}
+
+/// Parse the value of a segment from the protocol table that has a value.
+fn segment_from_value(name: &str, value: &str) -> Result<Segment, Cause> {
+    Ok(match name {
+        "ip4" => IP4(try!(value.parse())),
+        "ip6" => IP6(try!(value.parse())),
+        "ip6zone" => {
+            if value.is_empty() {
+                return Err(ValueError::Empty.into());
+            }
+            Ip6zone(value.to_owned())
+        }
+        "ipcidr" => Ipcidr(try!(value.parse())),
+        "udp" => Udp(try!(value.parse())),
+        "dccp" => Dccp(try!(value.parse())),
+        "sctp" => Sctp(try!(value.parse())),
+        "tcp" => Tcp(try!(value.parse())),
+        "memory" => Memory(try!(value.parse())),
+        "ipfs" | "p2p" => Ipfs(try!(multihash_from_str(value))),
+        "dns" => Dns(value.to_owned()),
+        "dns4" => Dns4(value.to_owned()),
+        "dns6" => Dns6(value.to_owned()),
+        "dnsaddr" => Dnsaddr(value.to_owned()),
+        "sni" => Sni(value.to_owned()),
+        "onion" => try!(onion_from_str_v2(value)),
+        "onion3" => try!(onion_from_str_v3(value)),
+        "garlic64" => Garlic64(try!(garlic64_from_str(value))),
+        "garlic32" => Garlic32(try!(garlic32_from_str(value))),
+        "unix" => Unix(try!(percent_decode(value))),
+        "certhash" => Certhash(try!(multihash_from_multibase(value))),
+        "http-path" => HttpPath(try!(percent_decode(value))),
+        _ => unreachable!("protocol {} has no parser", name),
+    })
+}
+
+/// Create a segment from the protocol table that has no value.
+fn segment_without_value(name: &str) -> Segment {
+    match name {
+        "udt" => Udt,
+        "utp" => Utp,
+        "http" => Http,
+        "https" => Https,
+        "quic" => Quic,
+        "quic-v1" => QuicV1,
+        "webtransport" => WebTransport,
+        "ws" => Ws,
+        "wss" => Wss,
+        "tls" => Tls,
+        "noise" => Noise,
+        "plaintextv2" => Plaintextv2,
+        "p2p-circuit" => P2pCircuit,
+        "webrtc" => WebRTC,
+        "webrtc-direct" => WebRTCDirect,
+        "p2p-stardust" => P2pStardust,
+        "p2p-webrtc-direct" => P2pWebRTCDirect,
+        "p2p-webrtc-star" => P2pWebRTCStar,
+        "p2p-websocket-star" => P2pWebSocketStar,
+        _ => unreachable!("protocol {} has no segment", name),
+    }
+}
+
+fn invalid_value(protocol: &str, (offset, value): (usize, &str), index: usize, cause: Cause) -> ParseError {
+    ParseError::InvalidValue {
+        protocol: protocol.to_owned(),
+        value: value.to_owned(),
+        offset: offset,
+        index: index,
+        cause: cause,
+    }
+}
+
+/// Take the value following the protocol name at `offset`, an empty final
+/// part is treated as a missing value rather than an empty one.
+fn next_value<'a>(parts: &mut Parts<'a>, protocol: &str, offset: usize, index: usize) -> Result<(usize, &'a str), ParseError> {
+    match parts.next() {
+        Some((_, "")) if parts.len() == 0 => (),
+        Some(part) => return Ok(part),
+        None => (),
+    }
+    Err(ParseError::MissingValue {
+        protocol: protocol.to_owned(),
+        offset: offset,
+        index: index,
+    })
+}
+
+fn registered_segment_from_parts(protocol: &dyn Protocol, parts: &mut Parts, offset: usize, index: usize) -> Result<Segment, ParseError> {
+    if protocol.size() == Size::Fixed(0) {
+        return Ok(Unknown(protocol.code(), Vec::new()));
+    }
+    let value = try!(next_value(parts, protocol.name(), offset, index));
+    let data = try!(protocol.parse_value(value.1)
+        .and_then(|value| join_value(protocol, value).map_err(Cause::from))
+        .map_err(|cause| invalid_value(protocol.name(), value, index, cause)));
+    Ok(Unknown(protocol.code(), data))
+}
+
+fn segment_from_parts(s: &str, parts: &mut Parts, index: usize, registry: &Registry) -> Result<Option<Segment>, ParseError> {
+    let (offset, name) = match parts.next() {
+        Some(part) => part,
+        None => return Ok(None),
+    };
+    if name.is_empty() && parts.len() == 0 {
+        return Err(ParseError::TrailingSlash { offset: offset - 1, index: index });
+    }
+    let unknown = || ParseError::UnknownProtocol {
+        name: name.to_owned(),
+        offset: offset,
+        index: index,
+    };
+
+    if name == "unix" {
+        // The path is the rest of the address, including the slash
+        // separating it from the segment name
+        if parts.len() == 0 {
+            return Err(ParseError::MissingValue {
+                protocol: name.to_owned(),
+                offset: offset,
+                index: index,
+            });
+        }
+        parts.for_each(drop);
+        let value = (offset + name.len(), &s[offset + name.len()..]);
+        return segment_from_value(name, value.1)
+            .map(Some)
+            .map_err(|cause| invalid_value(name, value, index, cause));
+    }
+
+    if let Some(code) = name.strip_prefix("x-") {
+        let code = try!(code.parse().map_err(|_| unknown()));
+        // Allowing these would give two unequal segments with the same
+        // binary encoding
+        if protocol_info(code).is_some() {
+            return Err(ParseError::BuiltInCode { code: code, offset: offset, index: index });
+        }
+        let data = match parts.next() {
+            Some(value) => try!(HEXLOWER_PERMISSIVE.decode(value.1.as_bytes())
+                .map_err(|err| invalid_value(name, value, index, err.into()))),
+            None => Vec::new(),
+        };
+        return Ok(Some(Unknown(code, data)));
+    }
+
+    match protocols::by_name(name) {
+        Some(protocol) if protocol.size == Size::Fixed(0) => {
+            Ok(Some(segment_without_value(name)))
         }
-        let mut strs = s[1..].split('/');
-        let mut segments = vec![];
-        while let Some(segment) = try!(segment_from_strs(&mut strs)) {
-            segments.push(segment);
+        Some(_) => {
+            let value = try!(next_value(parts, name, offset, index));
+            segment_from_value(name, value.1)
+                .map(Some)
+                .map_err(|cause| invalid_value(name, value, index, cause))
         }
-        Ok(MultiAddr::new(segments))
+        None => match registry.get_by_name(name) {
+            Some(protocol) => {
+                registered_segment_from_parts(protocol, parts, offset, index).map(Some)
+            }
+            None => Err(unknown()),
+        },
+    }
+}
+
+fn multiaddr_from_str(s: &str, registry: &Registry) -> Result<MultiAddr, ParseError> {
+    if s.is_empty() || s == "/" {
+        return Err(ParseError::Empty);
+    }
+    if !s.starts_with('/') {
+        return Err(ParseError::MissingLeadingSlash);
+    }
+    let mut offset = 1;
+    let mut parts = Vec::new();
+    for part in s[1..].split('/') {
+        parts.push((offset, part));
+        offset += part.len() + 1;
+    }
+    let mut parts = parts.into_iter();
+    let mut segments = vec![];
+    while let Some(segment) = try!(segment_from_parts(s, &mut parts, segments.len(), registry)) {
+        segments.push(segment);
+    }
+    Ok(MultiAddr::new(segments))
+}
+
+/// Supports only the built in protocols, use `Registry::parse` to also
+/// support registered protocols.
+impl FromStr for MultiAddr {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        multiaddr_from_str(s, &Registry::new())
+    }
+}
+
+impl Registry {
+    /// Parse a `MultiAddr` from its string representation, supporting the
+    /// protocols in this registry in addition to the built in protocols.
+    pub fn parse(&self, s: &str) -> Result<MultiAddr, ParseError> {
+        multiaddr_from_str(s, self)
     }
 }
 
@@ -63,7 +522,16 @@ mod tests {
 
     use mhash::{ MultiHash, MultiHashVariant };
 
-    use { MultiAddr, Segment };
+    use { MultiAddr, ParseError, Segment, ValueError };
+
+    fn value_error(s: &str) -> ValueError {
+        match s.parse::<MultiAddr>() {
+            Err(ParseError::InvalidValue { cause, .. }) => {
+                *cause.downcast::<ValueError>().unwrap()
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
 
     #[test]
     fn ip4() {
@@ -79,6 +547,33 @@ mod tests {
             "/ip6/2a02:6b8::11:11".parse().unwrap());
     }
 
+    #[test]
+    fn ip6zone() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::Ip6zone("eth0".to_owned()),
+                Segment::IP6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            ]),
+            "/ip6zone/eth0/ip6/fe80::1".parse().unwrap());
+    }
+
+    #[test]
+    fn ipcidr() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(10, 0, 0, 0)),
+                Segment::Ipcidr(8),
+            ]),
+            "/ip4/10.0.0.0/ipcidr/8".parse().unwrap());
+    }
+
+    #[test]
+    fn memory() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Memory(1234)]),
+            "/memory/1234".parse().unwrap());
+    }
+
     #[test]
     fn ipfs() {
         let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
@@ -91,4 +586,233 @@ mod tests {
             MultiAddr::new(vec![Segment::Ipfs(multihash)]),
             "/ipfs/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC".parse().unwrap());
     }
+
+    #[test]
+    fn p2p() {
+        let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
+            213, 46, 187, 137, 216, 91, 2, 162,
+            132, 148, 130, 3, 166, 47, 242, 131,
+            137, 197, 124, 159, 66, 190, 236, 78,
+            194, 13, 183, 106, 104, 145, 28, 11,
+        ]).unwrap();
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Ipfs(multihash)]),
+            "/p2p/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC".parse().unwrap());
+    }
+
+    #[test]
+    fn webtransport() {
+        let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
+            213, 46, 187, 137, 216, 91, 2, 162,
+            132, 148, 130, 3, 166, 47, 242, 131,
+            137, 197, 124, 159, 66, 190, 236, 78,
+            194, 13, 183, 106, 104, 145, 28, 11,
+        ]).unwrap();
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(1, 2, 3, 4)),
+                Segment::Udp(4001),
+                Segment::QuicV1,
+                Segment::WebTransport,
+                Segment::Certhash(multihash),
+            ]),
+            "/ip4/1.2.3.4/udp/4001/quic-v1/webtransport/certhash/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw".parse().unwrap());
+    }
+
+    #[test]
+    fn dns4() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::Dns4("example.com".to_owned()),
+                Segment::Tcp(4001),
+            ]),
+            "/dns4/example.com/tcp/4001".parse().unwrap());
+    }
+
+    #[test]
+    fn tls_sni_ws() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(1, 2, 3, 4)),
+                Segment::Tcp(443),
+                Segment::Tls,
+                Segment::Sni("example.com".to_owned()),
+                Segment::Ws,
+            ]),
+            "/ip4/1.2.3.4/tcp/443/tls/sni/example.com/ws".parse().unwrap());
+    }
+
+    #[test]
+    fn circuit_webrtc() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(1, 2, 3, 4)),
+                Segment::Udp(4001),
+                Segment::WebRTCDirect,
+                Segment::P2pCircuit,
+                Segment::WebRTC,
+            ]),
+            "/ip4/1.2.3.4/udp/4001/webrtc-direct/p2p-circuit/webrtc".parse().unwrap());
+    }
+
+    #[test]
+    fn onion() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Onion(
+                [154, 24, 8, 115, 6, 54, 144, 67, 9, 31],
+                1234)]),
+            "/onion/timaq4ygg2iegci7:1234".parse().unwrap());
+    }
+
+    #[test]
+    fn onion3() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Onion3([
+                173, 173, 236, 4, 11, 224, 71, 249, 101, 134, 104, 177,
+                26, 80, 79, 49, 85, 0, 31, 35, 26, 55, 245, 76, 68, 118,
+                192, 127, 180, 204, 19, 158, 215, 227, 3,
+            ], 1234)]),
+            "/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234".parse().unwrap());
+    }
+
+    #[test]
+    fn onion_too_short() {
+        assert_eq!(
+            ValueError::AddressTooShort(15),
+            value_error("/onion/timaq4ygg2iegci:1234"));
+    }
+
+    #[test]
+    fn onion_too_long() {
+        assert_eq!(
+            ValueError::AddressTooLong(58),
+            value_error("/onion3/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyydaa:1234"));
+    }
+
+    #[test]
+    fn onion_missing_port() {
+        assert_eq!(ValueError::MissingPort, value_error("/onion/timaq4ygg2iegci7"));
+    }
+
+    #[test]
+    fn garlic32() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Garlic32(vec![
+                239, 188, 212, 93, 12, 93, 199, 151, 129, 172, 111, 32, 234, 80, 85, 160,
+                54, 175, 180, 141, 69, 165, 46, 125, 104, 236, 125, 67, 56, 145, 158, 105,
+            ])]),
+            "/garlic32/566niximlxdzpanmn4qouucvua3k7neniwss47li5r6ugoertzuq".parse().unwrap());
+    }
+
+    #[test]
+    fn garlic32_too_short() {
+        // 55 characters only decode to 34 bytes
+        assert_eq!(
+            ValueError::AddressTooShort(55),
+            value_error("/garlic32/566niximlxdzpanmn4qouucvua3k7neniwss47li5r6ugoertzuqaaa"));
+    }
+
+    #[test]
+    fn garlic64_too_short() {
+        assert_eq!(ValueError::AddressTooShort(4), value_error("/garlic64/AAAA"));
+    }
+
+    #[test]
+    fn unknown() {
+        assert_eq!(
+            MultiAddr::new(vec![
+                Segment::Tcp(4001),
+                Segment::Unknown(999, vec![0xde, 0xad, 0xbe, 0xef]),
+            ]),
+            "/tcp/4001/x-999/deadbeef".parse().unwrap());
+    }
+
+    #[test]
+    fn unix() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Unix("/tmp/p2p.sock".to_owned())]),
+            "/unix/tmp/p2p.sock".parse().unwrap());
+    }
+
+    #[test]
+    fn unix_escaped() {
+        assert_eq!(
+            MultiAddr::new(vec![Segment::Unix("/tmp/100%.sock".to_owned())]),
+            "/unix/tmp/100%25.sock".parse().unwrap());
+    }
+
+    #[test]
+    fn unix_invalid_escape() {
+        assert_eq!(ValueError::InvalidPercentEscape, value_error("/unix/tmp/%+1.sock"));
+        assert_eq!(ValueError::InvalidPercentEscape, value_error("/unix/tmp/%1"));
+    }
+
+    #[test]
+    fn empty() {
+        match "".parse::<MultiAddr>() {
+            Err(ParseError::Empty) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match "/".parse::<MultiAddr>() {
+            Err(ParseError::Empty) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_leading_slash() {
+        match "ip4/1.2.3.4".parse::<MultiAddr>() {
+            Err(ParseError::MissingLeadingSlash) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn trailing_slash() {
+        match "/ip4/1.2.3.4/".parse::<MultiAddr>() {
+            Err(ParseError::TrailingSlash { offset: 12, index: 1 }) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_protocol() {
+        match "/ip4/1.2.3.4/tpc/80".parse::<MultiAddr>() {
+            Err(ParseError::UnknownProtocol { ref name, offset: 13, index: 1 }) if name == "tpc" => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn built_in_code() {
+        match "/x-6/0016".parse::<MultiAddr>() {
+            Err(ParseError::BuiltInCode { code: 6, offset: 1, index: 0 }) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match "/tcp/22/x-17/0016".parse::<MultiAddr>() {
+            Err(ParseError::BuiltInCode { code: 17, offset: 8, index: 1 }) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_value() {
+        match "/ip4/1.2.3.4/tcp".parse::<MultiAddr>() {
+            Err(ParseError::MissingValue { ref protocol, offset: 13, index: 1 }) if protocol == "tcp" => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        match "/ip4/1.2.3.4/tcp/".parse::<MultiAddr>() {
+            Err(ParseError::MissingValue { offset: 13, index: 1, .. }) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_value() {
+        let err = "/dns4/example.com/tcp/http".parse::<MultiAddr>().unwrap_err();
+        assert_eq!((22, 1), (err.offset(), err.index()));
+        assert_eq!(
+            "invalid tcp value \"http\" at byte 22 (segment 1): invalid digit found in string",
+            err.to_string());
+    }
 }
@@ -0,0 +1,318 @@
+use std::{ error, fmt, io };
+
+use varmint::{ ReadVarInt, WriteVarInt };
+
+use protocols;
+
+/// How the size of a protocol's binary value is determined.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Size {
+    /// The value is always exactly this many bytes, protocols without a
+    /// value use `Fixed(0)`.
+    Fixed(usize),
+
+    /// The value is prefixed with its length in bytes as a varint.
+    LengthPrefixed,
+}
+
+/// A user-defined protocol that can be added to a `Registry`.
+///
+/// Segments using a registered protocol are represented as
+/// `Segment::Unknown`, containing the protocol's code and the bytes following
+/// it in the binary representation (including the length prefix for
+/// `Size::LengthPrefixed` protocols).
+pub trait Protocol: fmt::Debug + Send + Sync {
+    /// The code used in the binary representation of this protocol.
+    fn code(&self) -> u64;
+
+    /// The name used in the string representation of this protocol.
+    fn name(&self) -> &str;
+
+    /// How the size of this protocol's binary value is determined.
+    fn size(&self) -> Size;
+
+    /// Convert the string representation of a value into its binary
+    /// representation, for `Size::LengthPrefixed` protocols this should not
+    /// include the length prefix. Not called for `Size::Fixed(0)` protocols.
+    fn parse_value(&self, value: &str) -> Result<Vec<u8>, Box<dyn error::Error + Send + Sync>>;
+
+    /// Write the string representation of a value given its binary
+    /// representation. Not called for `Size::Fixed(0)` protocols.
+    fn fmt_value(&self, value: &[u8], f: &mut fmt::Formatter) -> fmt::Result;
+
+    /// Check that a binary value read from a stream is valid, by default all
+    /// values of the correct size are accepted.
+    fn validate_value(&self, value: &[u8]) -> io::Result<()> {
+        let _ = value;
+        Ok(())
+    }
+}
+
+/// The error returned when a protocol can't be added to a `Registry`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RegisterError {
+    /// A protocol with this code has already been registered.
+    DuplicateCode(u64),
+
+    /// A protocol with this name has already been registered.
+    DuplicateName(String),
+}
+
+impl fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegisterError::DuplicateCode(code) => write!(f, "protocol code {} is already registered", code),
+            RegisterError::DuplicateName(ref name) => write!(f, "protocol name {} is already registered", name),
+        }
+    }
+}
+
+impl error::Error for RegisterError {
+    fn description(&self) -> &str {
+        match *self {
+            RegisterError::DuplicateCode(_) => "duplicate protocol code",
+            RegisterError::DuplicateName(_) => "duplicate protocol name",
+        }
+    }
+}
+
+/// A set of user-defined protocols to support in addition to those built in
+/// to this library.
+///
+/// Protocols can't use the same code or name as any of the built in protocols
+/// from the [protocol table](protocols/index.html).
+///
+/// # Examples
+///
+/// ```rust
+/// use std::{ error, fmt };
+/// use maddr::{ MultiAddr, Protocol, Registry, Segment, Size, WriteMultiAddr };
+///
+/// #[derive(Debug)]
+/// struct Shard;
+///
+/// impl Protocol for Shard {
+///     fn code(&self) -> u64 { 0x300000 }
+///     fn name(&self) -> &str { "shard" }
+///     fn size(&self) -> Size { Size::Fixed(1) }
+///     fn parse_value(&self, value: &str) -> Result<Vec<u8>, Box<dyn error::Error + Send + Sync>> {
+///         Ok(vec![value.parse()?])
+///     }
+///     fn fmt_value(&self, value: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+///         write!(f, "{}", value[0])
+///     }
+/// }
+///
+/// let mut registry = Registry::new();
+/// registry.register(Shard).unwrap();
+///
+/// let addr = registry.parse("/ip4/1.2.3.4/tcp/4001/shard/7").unwrap();
+/// assert_eq!(Some(&Segment::Unknown(0x300000, vec![7])), addr.segments().last());
+/// assert_eq!("/ip4/1.2.3.4/tcp/4001/shard/7", addr.display(Default::default()).with_registry(&registry).to_string());
+///
+/// let mut bytes = Vec::new();
+/// bytes.write_multiaddr(&addr).unwrap();
+/// assert_eq!(addr, registry.read_multiaddr(&mut &bytes[..]).unwrap());
+/// ```
+#[derive(Debug, Default)]
+pub struct Registry {
+    protocols: Vec<Box<dyn Protocol>>,
+}
+
+/// A registry without any protocols, for decoding with only the built in
+/// protocols.
+pub(crate) static EMPTY: Registry = Registry { protocols: Vec::new() };
+
+impl Registry {
+    /// Create a new `Registry` without any protocols.
+    pub fn new() -> Registry {
+        Registry { protocols: Vec::new() }
+    }
+
+    /// Add a protocol to this registry.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a protocol with the same code or name is built in or has
+    /// already been registered.
+    pub fn register<P: Protocol + 'static>(&mut self, protocol: P) -> Result<(), RegisterError> {
+        if protocols::by_code(protocol.code()).is_some() || self.get(protocol.code()).is_some() {
+            return Err(RegisterError::DuplicateCode(protocol.code()));
+        }
+        if protocols::by_name(protocol.name()).is_some() || self.get_by_name(protocol.name()).is_some() {
+            return Err(RegisterError::DuplicateName(protocol.name().to_owned()));
+        }
+        self.protocols.push(Box::new(protocol));
+        Ok(())
+    }
+
+    /// Get the registered protocol with the given code.
+    pub fn get(&self, code: u64) -> Option<&dyn Protocol> {
+        self.protocols.iter().find(|p| p.code() == code).map(|p| &**p)
+    }
+
+    /// Get the registered protocol with the given name.
+    pub fn get_by_name(&self, name: &str) -> Option<&dyn Protocol> {
+        self.protocols.iter().find(|p| p.name() == name).map(|p| &**p)
+    }
+
+    /// Get the binary value of a `Segment::Unknown` using a registered
+    /// protocol, without any length prefix.
+    pub fn value<'a>(&self, segment: &'a ::Segment) -> Option<&'a [u8]> {
+        match *segment {
+            ::Segment::Unknown(code, ref data) => {
+                self.get(code).and_then(|protocol| split_value(protocol, data))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Get the value from the bytes following a registered protocol's code, if
+/// they match the protocol's size.
+pub(crate) fn split_value<'a>(protocol: &dyn Protocol, data: &'a [u8]) -> Option<&'a [u8]> {
+    match protocol.size() {
+        Size::Fixed(size) if data.len() == size => Some(data),
+        Size::Fixed(_) => None,
+        Size::LengthPrefixed => {
+            let mut value = data;
+            match value.read_u64_varint() {
+                Ok(length) if length == value.len() as u64 => Some(value),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Create the bytes following a registered protocol's code from its value.
+pub(crate) fn join_value(protocol: &dyn Protocol, value: Vec<u8>) -> Result<Vec<u8>, String> {
+    match protocol.size() {
+        Size::Fixed(size) if value.len() == size => Ok(value),
+        Size::Fixed(size) => Err(format!(
+            "{} value should be {} bytes, was {} bytes",
+            protocol.name(), size, value.len())),
+        Size::LengthPrefixed => {
+            let mut data = Vec::with_capacity(value.len() + 10);
+            data.write_usize_varint(value.len()).expect("writing to Vec cannot fail");
+            data.extend_from_slice(&value);
+            Ok(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ error, fmt, str };
+
+    use { MultiAddr, Protocol, RegisterError, Registry, Segment, Size, WriteMultiAddr };
+
+    #[derive(Debug)]
+    struct Label;
+
+    impl Protocol for Label {
+        fn code(&self) -> u64 { 0x300001 }
+        fn name(&self) -> &str { "label" }
+        fn size(&self) -> Size { Size::LengthPrefixed }
+        fn parse_value(&self, value: &str) -> Result<Vec<u8>, Box<dyn error::Error + Send + Sync>> {
+            Ok(value.as_bytes().to_vec())
+        }
+        fn fmt_value(&self, value: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(try!(str::from_utf8(value).map_err(|_| fmt::Error)))
+        }
+    }
+
+    #[derive(Debug)]
+    struct Marker;
+
+    impl Protocol for Marker {
+        fn code(&self) -> u64 { 0x300002 }
+        fn name(&self) -> &str { "marker" }
+        fn size(&self) -> Size { Size::Fixed(0) }
+        fn parse_value(&self, _: &str) -> Result<Vec<u8>, Box<dyn error::Error + Send + Sync>> {
+            unreachable!()
+        }
+        fn fmt_value(&self, _: &[u8], _: &mut fmt::Formatter) -> fmt::Result {
+            unreachable!()
+        }
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(Label).unwrap();
+        registry.register(Marker).unwrap();
+        registry
+    }
+
+    fn multiaddr() -> MultiAddr {
+        MultiAddr::new(vec![
+            Segment::Unknown(0x300001, vec![3, b'f', b'o', b'o']),
+            Segment::Unknown(0x300002, vec![]),
+            Segment::Tcp(4001),
+        ])
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(multiaddr(), registry().parse("/label/foo/marker/tcp/4001").unwrap());
+    }
+
+    #[test]
+    fn display() {
+        let registry = registry();
+        assert_eq!(
+            "/label/foo/marker/tcp/4001",
+            multiaddr().display(Default::default()).with_registry(&registry).to_string());
+    }
+
+    #[test]
+    fn read() {
+        let mut buffer: &[u8] = &[
+            0x81, 0x80, 0xc0, 0x01, 3, b'f', b'o', b'o',
+            0x82, 0x80, 0xc0, 0x01,
+            6, 0x0f, 0xa1,
+        ];
+        assert_eq!(multiaddr(), registry().read_multiaddr(&mut buffer).unwrap());
+    }
+
+    #[test]
+    fn write() {
+        let mut buffer = Vec::new();
+        buffer.write_multiaddr(&multiaddr()).unwrap();
+        assert_eq!(buffer, vec![
+            0x81, 0x80, 0xc0, 0x01, 3, b'f', b'o', b'o',
+            0x82, 0x80, 0xc0, 0x01,
+            6, 0x0f, 0xa1,
+        ]);
+    }
+
+    #[test]
+    fn value() {
+        assert_eq!(Some(&b"foo"[..]), registry().value(&multiaddr().segments()[0]));
+    }
+
+    #[test]
+    fn duplicate() {
+        let mut registry = registry();
+        assert_eq!(Err(RegisterError::DuplicateCode(0x300001)), registry.register(Label));
+    }
+
+    #[derive(Debug)]
+    struct Tcp;
+
+    impl Protocol for Tcp {
+        fn code(&self) -> u64 { 6 }
+        fn name(&self) -> &str { "not-tcp" }
+        fn size(&self) -> Size { Size::Fixed(0) }
+        fn parse_value(&self, _: &str) -> Result<Vec<u8>, Box<dyn error::Error + Send + Sync>> {
+            unreachable!()
+        }
+        fn fmt_value(&self, _: &[u8], _: &mut fmt::Formatter) -> fmt::Result {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn built_in() {
+        assert_eq!(Err(RegisterError::DuplicateCode(6)), Registry::new().register(Tcp));
+    }
+}
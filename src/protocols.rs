@@ -0,0 +1,115 @@
+//! The table of protocols from the [multiaddr specification][protocols],
+//! generated at build time from a vendored copy of `protocols.csv`.
+//!
+//! [protocols]: https://github.com/multiformats/multiaddr/blob/master/protocols.csv
+
+use Size;
+
+/// An entry in the protocol table.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ProtocolInfo {
+    /// The code used in the binary representation of this protocol.
+    pub code: u64,
+
+    /// The name used in the string representation of this protocol.
+    pub name: &'static str,
+
+    /// How the size of this protocol's binary value is determined.
+    pub size: Size,
+}
+
+include!(concat!(env!("OUT_DIR"), "/protocols.rs"));
+
+/// Find the entry in the protocol table with the given code, where multiple
+/// names share a code the preferred name is returned.
+pub fn by_code(code: u64) -> Option<&'static ProtocolInfo> {
+    ALL.iter().find(|protocol| protocol.code == code)
+}
+
+/// Find the entry in the protocol table with the given name.
+pub fn by_name(name: &str) -> Option<&'static ProtocolInfo> {
+    ALL.iter().find(|protocol| protocol.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use varmint::ReadVarInt;
+
+    use { DisplayMode, MultiAddr, MultiAddrRef, ReadMultiAddr, Size, WriteMultiAddr };
+    use super::{ ALL, IPFS, P2P, by_code, by_name };
+
+    /// An example value for each protocol, a new protocol in the table will
+    /// fail `round_trip` until it's supported and added here.
+    fn example(name: &str) -> Option<&'static str> {
+        Some(match name {
+            "ip4" => "/1.2.3.4",
+            "tcp" | "udp" | "dccp" | "sctp" => "/4001",
+            "ip6" => "/2a02:6b8::11:11",
+            "ip6zone" => "/eth0",
+            "ipcidr" => "/24",
+            "dns" | "dns4" | "dns6" | "dnsaddr" | "sni" => "/example.com",
+            "unix" => "/tmp/p2p.sock",
+            "p2p" | "ipfs" => "/QmcgpsyWgH8Y8ajJz1Cu72KnS5uo2Aa2LpzU7kinSupNKC",
+            "onion" => "/timaq4ygg2iegci7:1234",
+            "onion3" => "/vww6ybal4bd7szmgncyruucpgfkqahzddi37ktceo3ah7ngmcopnpyyd:1234",
+            "garlic64" => concat!(
+                "/AwoRGB8mLTQ7QklQV15lbHN6gYiPlp2kq7K5wMfO1dzj6vH4~wYNFBsiKTA3PkVM",
+                "U1phaG92fYSLkpmgp661vMPK0djf5u30-wIJEBceJSwzOkFIT1ZdZGtyeYCHjpWc",
+                "o6qxuL~GzdTb4unw9~4FDBMaISgvNj1ES1JZYGdudXyDipGYn6attLvCydDX3uXs",
+                "8~oBCA8WHSQrMjlAR05VXGNqcXh~ho2Um6KpsLe-xczT2uHo7~b9BAsSGSAnLjU8",
+                "Q0pRWF9mbXR7gomQl56lrLO6wcjP1t3k6~L5AAcOFRwjKjE4P0ZNVFtiaXB3foWM",
+                "k5qhqK-2vcTL0tng5-71~AMKERgfJi00O0JJUFdeZWxzeoGIj5adpKuyucDHztXc",
+                "4-rx-P8GDRQbIikwNz5FTFNaYWhvdn2Ei5KZoKeutbzDytHY3-bt9PsCCRAXHiUs",
+                "MzpBSE9WXWRrcnmAh46VnKOqsbi~xs3U2-Lp8Pf-BQwTGiEoLzY9REtSWWBnbnV8",
+                "g4qR"),
+            "garlic32" => "/566niximlxdzpanmn4qouucvua3k7neniwss47li5r6ugoertzuq",
+            "certhash" => "/uEiDVLruJ2FsCooSUggOmL_KDicV8n0K-7E7CDbdqaJEcCw",
+            "http-path" => "/foo%2Fbar",
+            "memory" => "/1234",
+            "tls" | "noise" | "quic" | "quic-v1" | "webtransport" | "http"
+                | "https" | "ws" | "wss" | "p2p-websocket-star"
+                | "p2p-stardust" | "p2p-webrtc-star" | "p2p-webrtc-direct"
+                | "webrtc-direct" | "webrtc" | "p2p-circuit" | "plaintextv2"
+                | "udt" | "utp" => "",
+            _ => return None,
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        for protocol in ALL {
+            let value = example(protocol.name)
+                .unwrap_or_else(|| panic!("no example for {}", protocol.name));
+            let string = format!("/{}{}", protocol.name, value);
+            let mode = if *protocol == P2P { DisplayMode::P2p } else { DisplayMode::Ipfs };
+
+            let addr: MultiAddr = string.parse().unwrap();
+            assert_eq!(1, addr.segments().len(), "{}", string);
+            let segment = &addr.segments()[0];
+            assert_eq!(protocol.code, segment.code(), "{}", string);
+            assert_eq!(string, addr.display(mode).to_string());
+
+            let mut bytes = Vec::new();
+            bytes.write_multiaddr(&addr).unwrap();
+            assert_eq!(bytes.len(), addr.encoded_len(), "{}", string);
+            let mut value = &bytes[..];
+            assert_eq!(protocol.code, value.read_u64_varint().unwrap(), "{}", string);
+            match protocol.size {
+                Size::Fixed(size) => assert_eq!(size, value.len(), "{}", string),
+                Size::LengthPrefixed => {
+                    let length = value.read_u64_varint().unwrap();
+                    assert_eq!(length, value.len() as u64, "{}", string);
+                }
+            }
+            assert_eq!(addr, (&bytes[..]).read_multiaddr().unwrap(), "{}", string);
+            assert_eq!(addr.to_string(), MultiAddrRef::new(&bytes).unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(Some(&P2P), by_code(421));
+        assert_eq!(Some(&IPFS), by_name("ipfs"));
+        assert_eq!(None, by_code(999));
+    }
+}
@@ -1,20 +1,27 @@
 use std::io;
 use std::net::{ Ipv4Addr, Ipv6Addr };
 
-use varmint::ReadVarInt;
-use mhash::ReadMultiHash;
+use varmint::{ ReadVarInt, WriteVarInt };
+use mhash::{ MultiHash, ReadMultiHash };
 
-use { MultiAddr, Segment };
+use { MultiAddr, Registry, Segment, Size };
 use Segment::*;
 
 trait ReadHelper {
     fn read_u8(&mut self) -> io::Result<u8>;
     fn read_u16_be(&mut self) -> io::Result<u16>;
+    fn read_u64_be(&mut self) -> io::Result<u64>;
     fn read_ipv4addr(&mut self) -> io::Result<Ipv4Addr>;
     fn read_ipv6addr(&mut self) -> io::Result<Ipv6Addr>;
+    fn read_bytes(&mut self) -> io::Result<Vec<u8>>;
+    fn read_string(&mut self) -> io::Result<String>;
+    fn read_garlic(&mut self, min: usize, max: usize) -> io::Result<Vec<u8>>;
+    fn read_onion_port(&mut self) -> io::Result<u16>;
+    fn read_prefixed_multihash(&mut self) -> io::Result<MultiHash>;
     fn check_empty(&mut self) -> io::Result<()>;
-    fn read_segment(&mut self, code: u64) -> io::Result<Segment>;
-    fn try_read_segment(&mut self) -> io::Result<Option<Segment>>;
+    fn read_segment(&mut self, code: u64, registry: &Registry) -> io::Result<Segment>;
+    fn try_read_segment(&mut self, registry: &Registry) -> io::Result<Option<Segment>>;
+    fn read_segments(&mut self, registry: &Registry) -> io::Result<MultiAddr>;
 }
 
 impl<R: io::Read> ReadHelper for R {
@@ -30,6 +37,12 @@ impl<R: io::Read> ReadHelper for R {
         Ok(((u16::from(buffer[0])) << 8) + (u16::from(buffer[1])))
     }
 
+    fn read_u64_be(&mut self) -> io::Result<u64> {
+        let mut buffer = [0; 8];
+        try!(self.read_exact(&mut buffer));
+        Ok(u64::from_be_bytes(buffer))
+    }
+
     fn read_ipv4addr(&mut self) -> io::Result<Ipv4Addr> {
         let mut buffer = [0; 4];
         try!(self.read_exact(&mut buffer));
@@ -42,6 +55,49 @@ impl<R: io::Read> ReadHelper for R {
         Ok(Ipv6Addr::from(buffer))
     }
 
+    fn read_bytes(&mut self) -> io::Result<Vec<u8>> {
+        let length = try!(self.read_u64_varint());
+        let mut buffer = Vec::new();
+        let mut bytes = io::Read::take(self, length);
+        let read = try!(io::Read::read_to_end(&mut bytes, &mut buffer));
+        if read as u64 != length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated bytes"));
+        }
+        Ok(buffer)
+    }
+
+    fn read_string(&mut self) -> io::Result<String> {
+        String::from_utf8(try!(self.read_bytes()))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn read_garlic(&mut self, min: usize, max: usize) -> io::Result<Vec<u8>> {
+        let bytes = try!(self.read_bytes());
+        if bytes.len() < min {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Garlic address too short"));
+        }
+        if bytes.len() > max {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Garlic address too long"));
+        }
+        Ok(bytes)
+    }
+
+    fn read_onion_port(&mut self) -> io::Result<u16> {
+        let port = try!(self.read_u16_be());
+        if port == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid onion port 0"));
+        }
+        Ok(port)
+    }
+
+    fn read_prefixed_multihash(&mut self) -> io::Result<MultiHash> {
+        let length = try!(self.read_u64_varint());
+        let mut hash_bytes = io::Read::take(self, length);
+        let multihash = try!(hash_bytes.read_multihash());
+        try!(hash_bytes.check_empty());
+        Ok(multihash)
+    }
+
     fn check_empty(&mut self) -> io::Result<()> {
         let mut buffer = [0];
         if try!(self.read(&mut buffer)) == 0 {
@@ -51,38 +107,126 @@ impl<R: io::Read> ReadHelper for R {
         }
     }
 
-    fn read_segment(&mut self, code: u64) -> io::Result<Segment> {
+    fn read_segment(&mut self, code: u64, registry: &Registry) -> io::Result<Segment> {
         Ok(match code {
             4 => IP4(try!(self.read_ipv4addr())),
             6 => Tcp(try!(self.read_u16_be())),
-            17 => Udp(try!(self.read_u16_be())),
+            // Older versions of the protocol table, and this library, used 17
+            // for udp
+            17 | 273 => Udp(try!(self.read_u16_be())),
             33 => Dccp(try!(self.read_u16_be())),
             41 => IP6(try!(self.read_ipv6addr())),
+            42 => {
+                let zone = try!(self.read_string());
+                if zone.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty ip6zone"));
+                }
+                Ip6zone(zone)
+            }
+            43 => Ipcidr(try!(self.read_u8())),
+            53 => Dns(try!(self.read_string())),
+            54 => Dns4(try!(self.read_string())),
+            55 => Dns6(try!(self.read_string())),
+            56 => Dnsaddr(try!(self.read_string())),
             132 => Sctp(try!(self.read_u16_be())),
+            275 => P2pWebRTCStar,
+            276 => P2pWebRTCDirect,
+            277 => P2pStardust,
+            280 => WebRTCDirect,
+            281 => WebRTC,
+            290 => P2pCircuit,
             301 => Udt,
             302 => Utp,
-            421 => {
-                let length = try!(self.read_u64_varint());
-                let mut hash_bytes = io::Read::take(self, length);
-                let multihash = try!(hash_bytes.read_multihash());
-                try!(hash_bytes.check_empty());
-                Ipfs(multihash)
-            }
+            400 => Unix(try!(self.read_string())),
+            421 => Ipfs(try!(self.read_prefixed_multihash())),
             443 => Https,
+            444 => {
+                let mut hash = [0; 10];
+                try!(self.read_exact(&mut hash));
+                Onion(hash, try!(self.read_onion_port()))
+            }
+            445 => {
+                let mut key = [0; 35];
+                try!(self.read_exact(&mut key));
+                Onion3(key, try!(self.read_onion_port()))
+            }
+            446 => Garlic64(try!(self.read_garlic(386, 462))),
+            447 => {
+                // Either the 32 byte hash of a destination, or an encrypted
+                // lease set of at least 35 bytes
+                let bytes = try!(self.read_bytes());
+                if bytes.len() != 32 && bytes.len() < 35 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Garlic address too short"));
+                }
+                Garlic32(bytes)
+            }
+            448 => Tls,
+            449 => Sni(try!(self.read_string())),
+            454 => Noise,
+            460 => Quic,
+            461 => QuicV1,
+            465 => WebTransport,
+            466 => Certhash(try!(self.read_prefixed_multihash())),
+            477 => Ws,
+            478 => Wss,
+            479 => P2pWebSocketStar,
             480 => Http,
+            481 => HttpPath(try!(self.read_string())),
+            777 => Memory(try!(self.read_u64_be())),
+            7_367_777 => Plaintextv2,
             _ => {
-                return Err(io::Error::new(io::ErrorKind::Other, "Invalid code"))
+                let mut data = Vec::new();
+                match registry.get(code) {
+                    Some(protocol) => {
+                        let value = match protocol.size() {
+                            Size::Fixed(size) => {
+                                let mut value = vec![0; size];
+                                try!(self.read_exact(&mut value));
+                                value
+                            }
+                            Size::LengthPrefixed => {
+                                let value = try!(self.read_bytes());
+                                try!(data.write_usize_varint(value.len()));
+                                value
+                            }
+                        };
+                        try!(protocol.validate_value(&value));
+                        data.extend_from_slice(&value);
+                    }
+                    None => {
+                        // There's no way to know how long the value is, and
+                        // so where any following segments start
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("Unknown protocol code {}, its value length can't be determined", code)));
+                    }
+                }
+                Unknown(code, data)
             }
         })
     }
 
-    fn try_read_segment(&mut self) -> io::Result<Option<Segment>> {
+    fn try_read_segment(&mut self, registry: &Registry) -> io::Result<Option<Segment>> {
         if let Some(code) = try!(self.try_read_u64_varint()) {
-            Ok(Some(try!(self.read_segment(code))))
+            Ok(Some(try!(self.read_segment(code, registry))))
         } else {
             Ok(None)
         }
     }
+
+    fn read_segments(&mut self, registry: &Registry) -> io::Result<MultiAddr> {
+        // multiaddr lacks any header telling us how far to read, assume the
+        // io::Read used has been correctly sized externally to only return the
+        // expected bytes for the multiaddr and keep reading till EOF is
+        // reached when attempting to read a segment code (any other EOF is
+        // treated as an error).
+        let mut segments = Vec::new();
+        while let Some(segment) = try!(self.try_read_segment(registry)) {
+            segments.push(segment);
+        }
+        try!(self.check_empty());
+        Ok(MultiAddr::new(segments))
+    }
 }
 
 /// A trait to allow reading a `MultiAddr` from an object.
@@ -93,6 +237,9 @@ pub trait ReadMultiAddr {
     /// Read a `MultiAddr` from this object. Consumes the entire object as
     /// there's no way to detect the end of an encoded `MultiAddr`.
     ///
+    /// Supports only the built in protocols, use `Registry::read_multiaddr`
+    /// to also support registered protocols.
+    ///
     /// # Errors
     ///
     /// Any errors encountered when reading from the underlying `io::Read`
@@ -111,26 +258,78 @@ pub trait ReadMultiAddr {
     ///     buffer.read_multiaddr().unwrap());
     /// ```
     fn read_multiaddr(&mut self) -> io::Result<MultiAddr>;
+
+    /// Read a `MultiAddr` prefixed with its encoded length as a varint, as
+    /// used in libp2p identify messages and peer records. Only consumes the
+    /// bytes of this `MultiAddr`, so can be used to read multiple addresses
+    /// from a single stream.
+    ///
+    /// # Errors
+    ///
+    /// If the length prefix is greater than `max_length` an error of kind
+    /// `InvalidData` is returned without reading any further, otherwise as
+    /// for `read_multiaddr`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::{ MultiAddr, Segment, ReadMultiAddr };
+    ///
+    /// let mut buffer: &[u8] = &[3, 6, 0, 22, 3, 6, 0, 80];
+    /// assert_eq!(
+    ///     MultiAddr::from(Segment::Tcp(22)),
+    ///     buffer.read_multiaddr_length_prefixed(1024).unwrap());
+    /// assert_eq!(
+    ///     MultiAddr::from(Segment::Tcp(80)),
+    ///     buffer.read_multiaddr_length_prefixed(1024).unwrap());
+    /// ```
+    fn read_multiaddr_length_prefixed(&mut self, max_length: usize) -> io::Result<MultiAddr>;
 }
 
 impl<R: io::Read> ReadMultiAddr for R {
     fn read_multiaddr(&mut self) -> io::Result<MultiAddr> {
-        // multiaddr lacks any header telling us how far to read, assume the
-        // io::Read used has been correctly sized externally to only return the
-        // expected bytes for the multiaddr and keep reading till EOF is
-        // reached when attempting to read a segment code (any other EOF is
-        // treated as an error).
-        let mut segments = Vec::new();
-        while let Some(segment) = try!(self.try_read_segment()) {
-            segments.push(segment);
+        self.read_segments(&Registry::new())
+    }
+
+    fn read_multiaddr_length_prefixed(&mut self, max_length: usize) -> io::Result<MultiAddr> {
+        let length = try!(self.read_usize_varint());
+        if length > max_length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Multiaddr length {} exceeds maximum {}", length, max_length)));
         }
-        try!(self.check_empty());
-        Ok(MultiAddr::new(segments))
+        let mut buffer = vec![0; length];
+        try!(self.read_exact(&mut buffer));
+        (&buffer[..]).read_segments(&Registry::new())
+    }
+}
+
+/// Read the next segment from an already validated buffer, advancing it past
+/// the segment, returns `None` once the buffer is empty.
+pub(crate) fn read_next_segment(bytes: &mut &[u8], registry: &Registry) -> io::Result<Option<Segment>> {
+    bytes.try_read_segment(registry)
+}
+
+/// Decode the value of a segment with the given code from exactly the bytes
+/// following its code.
+pub(crate) fn read_segment_value(code: u64, mut bytes: &[u8], registry: &Registry) -> io::Result<Segment> {
+    let segment = try!(bytes.read_segment(code, registry));
+    try!(bytes.check_empty());
+    Ok(segment)
+}
+
+impl Registry {
+    /// Read a `MultiAddr` from the given stream, supporting the protocols in
+    /// this registry in addition to the built in protocols. Consumes the
+    /// entire stream, see `ReadMultiAddr::read_multiaddr`.
+    pub fn read_multiaddr<R: io::Read>(&self, reader: &mut R) -> io::Result<MultiAddr> {
+        reader.read_segments(self)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::io;
     use std::net::{ Ipv4Addr, Ipv6Addr };
 
     use mhash::{ MultiHash, MultiHashVariant };
@@ -158,6 +357,44 @@ mod tests {
             MultiAddr::new(vec![Segment::IP6(ip)]));
     }
 
+    #[test]
+    fn ip6zone() {
+        let mut buffer: &[u8] = &[
+            42, 4, b'e', b't', b'h', b'0',
+            41,
+            0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![
+                Segment::Ip6zone("eth0".to_owned()),
+                Segment::IP6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            ]));
+    }
+
+    #[test]
+    fn ipcidr() {
+        let mut buffer: &[u8] = &[4, 10, 0, 0, 0, 43, 8];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(10, 0, 0, 0)),
+                Segment::Ipcidr(8),
+            ]));
+    }
+
+    #[test]
+    fn memory() {
+        let mut buffer: &[u8] = &[
+            0b10001001, 0b00000110,
+            0, 0, 0, 0, 0, 0, 0x04, 0xd2,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![Segment::Memory(1234)]));
+    }
+
     #[test]
     fn ipfs() {
         let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
@@ -179,4 +416,221 @@ mod tests {
             buffer.read_multiaddr().unwrap(),
             MultiAddr::new(vec![Segment::Ipfs(multihash)]));
     }
+
+    #[test]
+    fn webtransport() {
+        let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
+            213, 46, 187, 137, 216, 91, 2, 162,
+            132, 148, 130, 3, 166, 47, 242, 131,
+            137, 197, 124, 159, 66, 190, 236, 78,
+            194, 13, 183, 106, 104, 145, 28, 11,
+        ]).unwrap();
+
+        let mut buffer: &[u8] = &[
+            0b11001101, 0b00000011,
+            0b11010001, 0b00000011,
+            0b11010010, 0b00000011, 34,
+            0x12, 32,
+            213, 46, 187, 137, 216, 91, 2, 162,
+            132, 148, 130, 3, 166, 47, 242, 131,
+            137, 197, 124, 159, 66, 190, 236, 78,
+            194, 13, 183, 106, 104, 145, 28, 11
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![
+                Segment::QuicV1,
+                Segment::WebTransport,
+                Segment::Certhash(multihash),
+            ]));
+    }
+
+    #[test]
+    fn dns4() {
+        let mut buffer: &[u8] = &[
+            54, 11,
+            b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+            6, 0x0f, 0xa1,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![
+                Segment::Dns4("example.com".to_owned()),
+                Segment::Tcp(4001),
+            ]));
+    }
+
+    #[test]
+    fn tls_sni_ws() {
+        let mut buffer: &[u8] = &[
+            4, 1, 2, 3, 4,
+            6, 0x01, 0xbb,
+            0b11000000, 0b00000011,
+            0b11000001, 0b00000011, 11,
+            b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+            0b11011101, 0b00000011,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![
+                Segment::IP4(Ipv4Addr::new(1, 2, 3, 4)),
+                Segment::Tcp(443),
+                Segment::Tls,
+                Segment::Sni("example.com".to_owned()),
+                Segment::Ws,
+            ]));
+    }
+
+    #[test]
+    fn wss_noise_plaintextv2() {
+        let mut buffer: &[u8] = &[
+            0b11011110, 0b00000011,
+            0b11000110, 0b00000011,
+            0b11100001, 0b11011000, 0b11000001, 0b00000011,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![
+                Segment::Wss,
+                Segment::Noise,
+                Segment::Plaintextv2,
+            ]));
+    }
+
+    #[test]
+    fn circuit_webrtc() {
+        let mut buffer: &[u8] = &[
+            0b10011000, 0b00000010,
+            0b10100010, 0b00000010,
+            0b10011001, 0b00000010,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![
+                Segment::WebRTCDirect,
+                Segment::P2pCircuit,
+                Segment::WebRTC,
+            ]));
+    }
+
+    #[test]
+    fn onion() {
+        let mut buffer: &[u8] = &[
+            0b10111100, 0b00000011,
+            154, 24, 8, 115, 6, 54, 144, 67, 9, 31,
+            0x04, 0xd2,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![Segment::Onion(
+                [154, 24, 8, 115, 6, 54, 144, 67, 9, 31],
+                1234)]));
+    }
+
+    #[test]
+    fn onion_port_zero() {
+        let mut buffer: &[u8] = &[
+            0b10111100, 0b00000011,
+            154, 24, 8, 115, 6, 54, 144, 67, 9, 31,
+            0, 0,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap_err().to_string(),
+            "Invalid onion port 0");
+    }
+
+    #[test]
+    fn onion3_port_zero() {
+        let mut buffer = vec![0b10111101, 0b00000011];
+        buffer.extend_from_slice(&[7; 35]);
+        buffer.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            (&buffer[..]).read_multiaddr().unwrap_err().to_string(),
+            "Invalid onion port 0");
+    }
+
+    #[test]
+    fn garlic32() {
+        let mut buffer: &[u8] = &[
+            0b10111111, 0b00000011, 32,
+            239, 188, 212, 93, 12, 93, 199, 151, 129, 172, 111, 32, 234, 80, 85, 160,
+            54, 175, 180, 141, 69, 165, 46, 125, 104, 236, 125, 67, 56, 145, 158, 105,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![Segment::Garlic32(vec![
+                239, 188, 212, 93, 12, 93, 199, 151, 129, 172, 111, 32, 234, 80, 85, 160,
+                54, 175, 180, 141, 69, 165, 46, 125, 104, 236, 125, 67, 56, 145, 158, 105,
+            ])]));
+    }
+
+    #[test]
+    fn garlic32_too_short() {
+        let mut buffer: &[u8] = &[0b10111111, 0b00000011, 3, 1, 2, 3];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap_err().to_string(),
+            "Garlic address too short");
+    }
+
+    #[test]
+    fn unknown() {
+        let mut buffer: &[u8] = &[
+            6, 0x0f, 0xa1,
+            0b11100111, 0b00000111, 0xde, 0xad, 0xbe, 0xef,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap_err().to_string(),
+            "Unknown protocol code 999, its value length can't be determined");
+    }
+
+    #[test]
+    fn udp_legacy() {
+        let mut buffer: &[u8] = &[17, 0x0f, 0xa1];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![Segment::Udp(4001)]));
+    }
+
+    #[test]
+    fn unix() {
+        let mut buffer: &[u8] = &[
+            0b10010000, 0b00000011, 5,
+            b'/', b't', b'm', b'p', b'/',
+        ];
+        assert_eq!(
+            buffer.read_multiaddr().unwrap(),
+            MultiAddr::new(vec![Segment::Unix("/tmp/".to_owned())]));
+    }
+
+    #[test]
+    fn length_prefixed() {
+        let mut buffer: &[u8] = &[
+            3, 6, 0x0f, 0xa1,
+            5, 4, 1, 2, 3, 4,
+            0xff,
+        ];
+        assert_eq!(
+            buffer.read_multiaddr_length_prefixed(16).unwrap(),
+            MultiAddr::new(vec![Segment::Tcp(4001)]));
+        assert_eq!(
+            buffer.read_multiaddr_length_prefixed(16).unwrap(),
+            MultiAddr::new(vec![Segment::IP4(Ipv4Addr::new(1, 2, 3, 4))]));
+        assert_eq!(buffer, &[0xff]);
+    }
+
+    #[test]
+    fn length_prefixed_too_long() {
+        let mut buffer: &[u8] = &[5, 4, 1, 2, 3, 4];
+        assert_eq!(
+            buffer.read_multiaddr_length_prefixed(4).unwrap_err().kind(),
+            io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn length_prefixed_truncated() {
+        let mut buffer: &[u8] = &[5, 4, 1, 2, 3];
+        assert_eq!(
+            buffer.read_multiaddr_length_prefixed(16).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof);
+    }
 }
@@ -2,6 +2,8 @@ use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 use mhash::MultiHash;
 
+use protocols::{ self, ProtocolInfo };
+
 #[allow(variant_size_differences)]
 #[derive(PartialEq, Eq, Clone, Hash)]
 /// The possible multiaddr segments.
@@ -23,13 +25,51 @@ use mhash::MultiHash;
 ///
 /// Look at the [implementations](#implementations) section below for more.
 pub enum Segment {
+    /// A hash of a TLS certificate, used by WebTransport and WebRTC to
+    /// authenticate self-signed certificates.
+    /// The argument is the multihash of the certificate.
+    Certhash(MultiHash),
+
     /// Datagram Congestion Control Protocol, a transport layer protocol.
     /// The argument is the port number.
     Dccp(u16),
 
+    /// Domain Name System, a name resolution protocol.
+    /// The argument is a hostname that may resolve to either IPv4 or IPv6
+    /// addresses.
+    Dns(String),
+
+    /// Domain Name System, a name resolution protocol.
+    /// The argument is a hostname that should only be resolved to IPv4
+    /// addresses.
+    Dns4(String),
+
+    /// Domain Name System, a name resolution protocol.
+    /// The argument is a hostname that should only be resolved to IPv6
+    /// addresses.
+    Dns6(String),
+
+    /// DNS-based multiaddr resolution, the `TXT` records of
+    /// `_dnsaddr.<hostname>` contain further multiaddrs.
+    /// The argument is the hostname.
+    Dnsaddr(String),
+
+    /// The Invisible Internet Project, an anonymous network layer protocol.
+    /// The argument is a base32 address, either the 32 byte hash of a
+    /// destination or an encrypted lease set of at least 35 bytes.
+    Garlic32(Vec<u8>),
+
+    /// The Invisible Internet Project, an anonymous network layer protocol.
+    /// The argument is a full destination of at least 386 bytes.
+    Garlic64(Vec<u8>),
+
     /// Hypertext Transfer Protocol, an application layer protocol.
     Http,
 
+    /// The path to a resource on a preceding HTTP server.
+    /// The argument is the unescaped path.
+    HttpPath(String),
+
     /// Hypertext Transfer Protocol layered on top of Transport Layer Security,
     /// an application layer protocol.
     Https,
@@ -42,18 +82,84 @@ pub enum Segment {
     /// The argument is the IPv6 address.
     IP6(Ipv6Addr),
 
+    /// IPv6 zone, the scope of a following link-local IPv6 address.
+    /// The argument is the zone identifier, either an interface name or
+    /// index.
+    Ip6zone(String),
+
+    /// Classless Inter-Domain Routing mask for a preceding IP address.
+    /// The argument is the prefix length in bits.
+    Ipcidr(u8),
+
     /// The InterPlanetary File System, an application layer protocol.
     /// The argument is the public hash of an IPFS node.
+    ///
+    /// The multicodec table now names this protocol `p2p`, both `/ipfs/` and
+    /// `/p2p/` are accepted when parsing, see `DisplayMode` for choosing
+    /// which is used when displaying.
     Ipfs(MultiHash),
 
+    /// An in-process transport, used for testing without binding real
+    /// sockets.
+    /// The argument is an arbitrary identifier for the listener.
+    Memory(u64),
+
+    /// Noise, a security protocol for authenticating and encrypting
+    /// connections.
+    Noise,
+
+    /// Tor onion service version 2, an anonymous network layer protocol.
+    /// The arguments are the 10 byte hash of the service key and the port
+    /// number.
+    Onion([u8; 10], u16),
+
+    /// Tor onion service version 3, an anonymous network layer protocol.
+    /// The arguments are the 35 byte encoded service key and the port number.
+    Onion3([u8; 35], u16),
+
+    /// Circuit relay, the preceding segments address a relay node and the
+    /// following segments address the destination reached through it.
+    P2pCircuit,
+
+    /// Deprecated stardust rendezvous protocol.
+    P2pStardust,
+
+    /// Deprecated WebRTC transport without a rendezvous server.
+    P2pWebRTCDirect,
+
+    /// Deprecated WebRTC transport using a rendezvous server.
+    P2pWebRTCStar,
+
+    /// Deprecated WebSockets transport using a rendezvous server.
+    P2pWebSocketStar,
+
+    /// Plaintext version 2, an insecure handshake exchanging public keys, only
+    /// intended for testing.
+    Plaintextv2,
+
+    /// QUIC, a transport layer protocol built on UDP, using the pre-standard
+    /// draft-29 wire format.
+    Quic,
+
+    /// QUIC, a transport layer protocol built on UDP, using the version 1 wire
+    /// format from RFC 9000.
+    QuicV1,
+
     /// Stream Control Transmission Protocol, a transport layer protocol.
     /// The argument is the port number.
     Sctp(u16),
 
+    /// Server Name Indication, the hostname sent in the TLS handshake.
+    /// The argument is the hostname.
+    Sni(String),
+
     /// Transmission Control Protocol, a transport layer protocol.
     /// The argument is the port number.
     Tcp(u16),
 
+    /// Transport Layer Security, a security protocol.
+    Tls,
+
     /// User Datagram Protocol, a transport layer protocol.
     /// The argument is the port number.
     Udp(u16),
@@ -61,43 +167,112 @@ pub enum Segment {
     /// UDP-based Data Transfer Protocol, an application layer protocol.
     Udt,
 
+    /// A segment using a protocol that isn't built in, either a protocol from a
+    /// `Registry` or one this library doesn't recognise, kept so that
+    /// addresses from newer peers can be forwarded without losing data. The
+    /// arguments are the protocol code, which must not be that of a built in
+    /// protocol, and the bytes following the code in the binary encoding,
+    /// including any length prefix.
+    ///
+    /// The string representation is `/x-<code>/<hex bytes>`, where the hex
+    /// bytes may be empty, or the registered protocol's own representation.
+    /// Reading a binary address only produces this for protocols from a
+    /// `Registry`, as the size of an unregistered protocol's value can't be
+    /// determined.
+    Unknown(u64, Vec<u8>),
+
+    /// Unix domain sockets, an inter-process communication protocol.
+    /// The argument is the filesystem path of the socket, this should be an
+    /// absolute path as the string representation always starts the path with
+    /// `/`, a relative path like `p2p.sock` is displayed as `/unix/p2p.sock`
+    /// which parses back to the absolute path `/p2p.sock`.
+    Unix(String),
+
     /// Micro Transport Protocol, an application? layer protocol.
     Utp,
+
+    /// WebTransport, an application layer protocol built on HTTP/3.
+    WebTransport,
+
+    /// WebRTC, a browser-to-browser transport protocol which relies on an
+    /// external signalling channel.
+    WebRTC,
+
+    /// WebRTC without external signalling, the node listens on the preceding
+    /// UDP address and browsers connect to it directly.
+    WebRTCDirect,
+
+    /// WebSockets, an application layer protocol built on HTTP.
+    Ws,
+
+    /// WebSockets layered on top of Transport Layer Security, an application
+    /// layer protocol.
+    Wss,
 }
 
 impl Segment {
+    /// The entry in the protocol table for this segment's protocol, `None`
+    /// for `Segment::Unknown`.
+    pub fn protocol(&self) -> Option<&'static ProtocolInfo> {
+        match *self {
+            Segment::Certhash(_) => Some(&protocols::CERTHASH),
+            Segment::Dccp(_) => Some(&protocols::DCCP),
+            Segment::Dns(_) => Some(&protocols::DNS),
+            Segment::Dns4(_) => Some(&protocols::DNS4),
+            Segment::Dns6(_) => Some(&protocols::DNS6),
+            Segment::Dnsaddr(_) => Some(&protocols::DNSADDR),
+            Segment::Garlic32(_) => Some(&protocols::GARLIC32),
+            Segment::Garlic64(_) => Some(&protocols::GARLIC64),
+            Segment::Http => Some(&protocols::HTTP),
+            Segment::HttpPath(_) => Some(&protocols::HTTP_PATH),
+            Segment::Https => Some(&protocols::HTTPS),
+            Segment::IP4(_) => Some(&protocols::IP4),
+            Segment::IP6(_) => Some(&protocols::IP6),
+            Segment::Ip6zone(_) => Some(&protocols::IP6ZONE),
+            Segment::Ipcidr(_) => Some(&protocols::IPCIDR),
+            Segment::Ipfs(_) => Some(&protocols::IPFS),
+            Segment::Memory(_) => Some(&protocols::MEMORY),
+            Segment::Noise => Some(&protocols::NOISE),
+            Segment::Onion(..) => Some(&protocols::ONION),
+            Segment::Onion3(..) => Some(&protocols::ONION3),
+            Segment::P2pCircuit => Some(&protocols::P2P_CIRCUIT),
+            Segment::P2pStardust => Some(&protocols::P2P_STARDUST),
+            Segment::P2pWebRTCDirect => Some(&protocols::P2P_WEBRTC_DIRECT),
+            Segment::P2pWebRTCStar => Some(&protocols::P2P_WEBRTC_STAR),
+            Segment::P2pWebSocketStar => Some(&protocols::P2P_WEBSOCKET_STAR),
+            Segment::Plaintextv2 => Some(&protocols::PLAINTEXTV2),
+            Segment::Quic => Some(&protocols::QUIC),
+            Segment::QuicV1 => Some(&protocols::QUIC_V1),
+            Segment::Sctp(_) => Some(&protocols::SCTP),
+            Segment::Sni(_) => Some(&protocols::SNI),
+            Segment::Tcp(_) => Some(&protocols::TCP),
+            Segment::Tls => Some(&protocols::TLS),
+            Segment::Udp(_) => Some(&protocols::UDP),
+            Segment::Udt => Some(&protocols::UDT),
+            Segment::Unix(_) => Some(&protocols::UNIX),
+            Segment::Unknown(..) => None,
+            Segment::Utp => Some(&protocols::UTP),
+            Segment::WebRTC => Some(&protocols::WEBRTC),
+            Segment::WebRTCDirect => Some(&protocols::WEBRTC_DIRECT),
+            Segment::WebTransport => Some(&protocols::WEBTRANSPORT),
+            Segment::Ws => Some(&protocols::WS),
+            Segment::Wss => Some(&protocols::WSS),
+        }
+    }
+
     /// The code used in the binary representation of this segment.
     pub fn code(&self) -> u64 {
         match *self {
-            Segment::Dccp(_) => 33,
-            Segment::Http => 480,
-            Segment::Https => 443,
-            Segment::IP4(_) => 4,
-            Segment::IP6(_) => 41,
-            Segment::Ipfs(_) => 421,
-            Segment::Sctp(_) => 132,
-            Segment::Tcp(_) => 6,
-            Segment::Udp(_) => 17,
-            Segment::Udt => 301,
-            Segment::Utp => 302,
+            Segment::Unknown(code, _) => code,
+            _ => self.protocol().expect("only unknown segments lack a protocol").code,
         }
     }
 
-    /// The name used in the string representation of this segment.
+    /// The name used in the string representation of this segment, for
+    /// `Segment::Unknown` this is just a placeholder, the string
+    /// representation uses `x-<code>` instead.
     pub fn name(&self) -> &'static str {
-        match *self {
-            Segment::Dccp(_) => "dccp",
-            Segment::Http => "http",
-            Segment::Https => "https",
-            Segment::IP4(_) => "ip4",
-            Segment::IP6(_) => "ip6",
-            Segment::Ipfs(_) => "ipfs",
-            Segment::Sctp(_) => "sctp",
-            Segment::Tcp(_) => "tcp",
-            Segment::Udp(_) => "udp",
-            Segment::Udt => "udt",
-            Segment::Utp => "utp",
-        }
+        self.protocol().map_or("unknown", |protocol| protocol.name)
     }
 }
 
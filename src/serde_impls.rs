@@ -0,0 +1,118 @@
+//! Serde support, human readable formats use the string representation and
+//! compact formats use the binary representation.
+
+use std::cmp;
+use std::fmt;
+
+use serde::de::{ self, Deserialize, Deserializer, SeqAccess, Visitor };
+use serde::ser::{ Serialize, Serializer };
+
+use { MultiAddr, ReadMultiAddr, Segment, WriteMultiAddr };
+use write::write_single_segment;
+
+struct MultiAddrVisitor;
+
+impl<'de> Visitor<'de> for MultiAddrVisitor {
+    type Value = MultiAddr;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a multiaddr")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<MultiAddr, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, mut value: &[u8]) -> Result<MultiAddr, E> {
+        value.read_multiaddr().map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<MultiAddr, A::Error> {
+        // Some formats serialize bytes as a sequence, limit the preallocation
+        // as the size hint is untrusted
+        let mut bytes = Vec::with_capacity(cmp::min(seq.size_hint().unwrap_or(0), 1024));
+        while let Some(byte) = try!(seq.next_element()) {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+impl Serialize for MultiAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            let mut bytes = Vec::with_capacity(self.encoded_len());
+            bytes.write_multiaddr(self).expect("writing to a Vec cannot fail");
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MultiAddr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MultiAddr, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(MultiAddrVisitor)
+        } else {
+            deserializer.deserialize_bytes(MultiAddrVisitor)
+        }
+    }
+}
+
+impl Serialize for Segment {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            let mut bytes = Vec::with_capacity(self.encoded_len());
+            write_single_segment(&mut bytes, self).expect("writing to a Vec cannot fail");
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Segment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Segment, D::Error> {
+        let multiaddr = try!(MultiAddr::deserialize(deserializer));
+        let len = multiaddr.segments().len();
+        match multiaddr.split_off_last() {
+            Some((ref rest, segment)) if rest.segments().is_empty() => Ok(segment),
+            _ => Err(de::Error::invalid_length(len, &"a single segment")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_test::{ Compact, Configure, Readable, Token, assert_de_tokens_error, assert_tokens };
+
+    use { MultiAddr, Segment };
+
+    #[test]
+    fn multiaddr() {
+        let addr: MultiAddr = "/ip4/1.2.3.4/tcp/22".parse().unwrap();
+        assert_tokens(&addr.clone().readable(), &[Token::Str("/ip4/1.2.3.4/tcp/22")]);
+        assert_tokens(&addr.compact(), &[Token::Bytes(&[4, 1, 2, 3, 4, 6, 0, 22])]);
+    }
+
+    #[test]
+    fn segment() {
+        let segment = Segment::Dns4("example.com".to_owned());
+        assert_tokens(&segment.clone().readable(), &[Token::Str("/dns4/example.com")]);
+        assert_tokens(&segment.compact(), &[Token::Bytes(b"\x36\x0bexample.com")]);
+    }
+
+    #[test]
+    fn invalid() {
+        assert_de_tokens_error::<Readable<MultiAddr>>(
+            &[Token::Str("/tcp/http")],
+            "invalid tcp value \"http\" at byte 5 (segment 0): invalid digit found in string");
+        assert_de_tokens_error::<Readable<Segment>>(
+            &[Token::Str("/ip4/1.2.3.4/tcp/22")],
+            "invalid length 2, expected a single segment");
+        assert_de_tokens_error::<Compact<MultiAddr>>(
+            &[Token::Bytes(&[4, 1, 2])],
+            "failed to fill whole buffer");
+    }
+}
@@ -0,0 +1,242 @@
+use std::convert::TryFrom;
+use std::{ error, fmt };
+use std::net::{ IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6 };
+
+use { MultiAddr, Segment };
+
+/// The transport protocol of a `MultiAddr` converted to a `SocketAddr`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Transport {
+    /// A `/tcp/<port>` address.
+    Tcp,
+    /// A `/udp/<port>` address.
+    Udp,
+}
+
+/// The reason a `MultiAddr` couldn't be converted to a `SocketAddr`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SocketAddrError {
+    /// The address didn't start with an `ip4` or `ip6` segment, optionally
+    /// preceded by an `ip6zone` segment.
+    MissingIp {
+        /// The segment found instead, if any.
+        found: Option<Segment>,
+    },
+
+    /// The IP segment wasn't followed by a `tcp` or `udp` segment.
+    MissingTransport {
+        /// The segment found instead, if any.
+        found: Option<Segment>,
+    },
+
+    /// The `ip6zone` wasn't a numeric interface index, `SocketAddrV6` can't
+    /// represent interface names.
+    InvalidZone {
+        /// The zone.
+        zone: String,
+    },
+
+    /// There were segments after the `tcp` or `udp` segment.
+    TrailingSegments {
+        /// The trailing segments.
+        rest: MultiAddr,
+    },
+}
+
+impl Transport {
+    fn segment(self, port: u16) -> Segment {
+        match self {
+            Transport::Tcp => Segment::Tcp(port),
+            Transport::Udp => Segment::Udp(port),
+        }
+    }
+}
+
+impl MultiAddr {
+    /// Attempt to convert this `MultiAddr` into a socket address along with
+    /// its transport protocol, this is only possible for addresses of the
+    /// form `[/ip6zone/<zone>]/(ip4|ip6)/<addr>/(tcp|udp)/<port>`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SocketAddrError` describing the first segment that doesn't
+    /// fit this form.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::{ MultiAddr, SocketAddrError, Transport };
+    ///
+    /// let addr: MultiAddr = "/ip4/1.2.3.4/udp/53".parse().unwrap();
+    /// let (socket_addr, transport) = addr.to_transport_addr().unwrap();
+    /// assert_eq!("1.2.3.4:53", socket_addr.to_string());
+    /// assert_eq!(Transport::Udp, transport);
+    ///
+    /// let addr: MultiAddr = "/ip4/1.2.3.4/tcp/80/http".parse().unwrap();
+    /// assert_eq!(
+    ///     "unexpected trailing segments /http after socket address",
+    ///     addr.to_transport_addr().unwrap_err().to_string());
+    /// ```
+    pub fn to_transport_addr(&self) -> Result<(SocketAddr, Transport), SocketAddrError> {
+        let (scope_id, rest) = match self.segments().split_first() {
+            Some((&Segment::Ip6zone(ref zone), rest)) => {
+                match zone.parse() {
+                    Ok(scope_id) => (Some(scope_id), rest),
+                    Err(_) => return Err(SocketAddrError::InvalidZone { zone: zone.clone() }),
+                }
+            }
+            _ => (None, self.segments()),
+        };
+
+        let (ip, rest) = match (rest.split_first(), scope_id) {
+            (Some((&Segment::IP4(ip), rest)), None) => (IpAddr::V4(ip), rest),
+            (Some((&Segment::IP6(ip), rest)), _) => (IpAddr::V6(ip), rest),
+            (found, _) => {
+                return Err(SocketAddrError::MissingIp {
+                    found: found.map(|(segment, _)| segment.clone()),
+                });
+            }
+        };
+
+        let (port, transport, rest) = match rest.split_first() {
+            Some((&Segment::Tcp(port), rest)) => (port, Transport::Tcp, rest),
+            Some((&Segment::Udp(port), rest)) => (port, Transport::Udp, rest),
+            found => {
+                return Err(SocketAddrError::MissingTransport {
+                    found: found.map(|(segment, _)| segment.clone()),
+                });
+            }
+        };
+
+        if !rest.is_empty() {
+            return Err(SocketAddrError::TrailingSegments { rest: MultiAddr::new(rest.to_vec()) });
+        }
+
+        let socket_addr = match ip {
+            IpAddr::V4(ip) => SocketAddr::V4(SocketAddrV4::new(ip, port)),
+            IpAddr::V6(ip) => SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id.unwrap_or(0))),
+        };
+        Ok((socket_addr, transport))
+    }
+
+    /// Create a `MultiAddr` from a socket address and transport protocol, a
+    /// non-zero IPv6 scope id is represented as a leading `ip6zone` segment.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::net::SocketAddr;
+    /// use maddr::{ MultiAddr, Transport };
+    ///
+    /// let socket_addr: SocketAddr = "[::1]:53".parse().unwrap();
+    /// assert_eq!(
+    ///     "/ip6/::1/udp/53",
+    ///     MultiAddr::from_transport_addr(socket_addr, Transport::Udp).to_string());
+    /// ```
+    pub fn from_transport_addr(socket_addr: SocketAddr, transport: Transport) -> MultiAddr {
+        let mut segments = Vec::with_capacity(3);
+        if let SocketAddr::V6(ref socket_addr) = socket_addr {
+            if socket_addr.scope_id() != 0 {
+                segments.push(Segment::Ip6zone(socket_addr.scope_id().to_string()));
+            }
+        }
+        segments.push(socket_addr.ip().into());
+        segments.push(transport.segment(socket_addr.port()));
+        MultiAddr::new(segments)
+    }
+}
+
+/// Converts to a `/tcp` address, use `MultiAddr::from_transport_addr` for
+/// `/udp`.
+impl From<SocketAddr> for MultiAddr {
+    fn from(socket_addr: SocketAddr) -> MultiAddr {
+        MultiAddr::from_transport_addr(socket_addr, Transport::Tcp)
+    }
+}
+
+/// Accepts both `/tcp` and `/udp` addresses, use
+/// `MultiAddr::to_transport_addr` to find out which it was.
+impl<'a> TryFrom<&'a MultiAddr> for SocketAddr {
+    type Error = SocketAddrError;
+
+    fn try_from(multiaddr: &'a MultiAddr) -> Result<SocketAddr, SocketAddrError> {
+        multiaddr.to_transport_addr().map(|(socket_addr, _)| socket_addr)
+    }
+}
+
+impl fmt::Display for SocketAddrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SocketAddrError::MissingIp { found: Some(ref segment) } =>
+                write!(f, "expected an ip4 or ip6 segment, found {}", segment),
+            SocketAddrError::MissingIp { found: None } =>
+                f.write_str("expected an ip4 or ip6 segment, found end of address"),
+            SocketAddrError::MissingTransport { found: Some(ref segment) } =>
+                write!(f, "expected a tcp or udp segment, found {}", segment),
+            SocketAddrError::MissingTransport { found: None } =>
+                f.write_str("expected a tcp or udp segment, found end of address"),
+            SocketAddrError::InvalidZone { ref zone } =>
+                write!(f, "ip6zone {:?} is not a numeric interface index", zone),
+            SocketAddrError::TrailingSegments { ref rest } =>
+                write!(f, "unexpected trailing segments {} after socket address", rest),
+        }
+    }
+}
+
+impl error::Error for SocketAddrError {
+    fn description(&self) -> &str {
+        match *self {
+            SocketAddrError::MissingIp { .. } => "missing ip segment",
+            SocketAddrError::MissingTransport { .. } => "missing tcp or udp segment",
+            SocketAddrError::InvalidZone { .. } => "non-numeric ip6zone",
+            SocketAddrError::TrailingSegments { .. } => "trailing segments",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::net::{ Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6 };
+
+    use { MultiAddr, Segment, SocketAddrError, Transport };
+
+    #[test]
+    fn tcp4() {
+        let addr = MultiAddr::from(Ipv4Addr::new(1, 2, 3, 4)) + Segment::Tcp(80);
+        let socket_addr: SocketAddr = "1.2.3.4:80".parse().unwrap();
+        assert_eq!(Ok(socket_addr), SocketAddr::try_from(&addr));
+        assert_eq!(addr, MultiAddr::from(socket_addr));
+    }
+
+    #[test]
+    fn udp6_zone() {
+        let addr = MultiAddr::from(Segment::Ip6zone("3".to_owned()))
+            + Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)
+            + Segment::Udp(53);
+        let socket_addr = SocketAddr::V6(
+            SocketAddrV6::new(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1), 53, 0, 3));
+        assert_eq!(Ok((socket_addr, Transport::Udp)), addr.to_transport_addr());
+        assert_eq!(addr, MultiAddr::from_transport_addr(socket_addr, Transport::Udp));
+    }
+
+    #[test]
+    fn errors() {
+        let ip4 = MultiAddr::from(Ipv4Addr::new(1, 2, 3, 4));
+        assert_eq!(
+            Err(SocketAddrError::MissingIp { found: Some(Segment::Dns4("a".to_owned())) }),
+            SocketAddr::try_from(&(MultiAddr::from(Segment::Dns4("a".to_owned())) + Segment::Tcp(80))));
+        assert_eq!(
+            Err(SocketAddrError::MissingIp { found: Some(Segment::IP4(Ipv4Addr::new(1, 2, 3, 4))) }),
+            SocketAddr::try_from(&(MultiAddr::from(Segment::Ip6zone("3".to_owned())) + ip4.clone())));
+        assert_eq!(
+            Err(SocketAddrError::MissingTransport { found: None }),
+            SocketAddr::try_from(&ip4));
+        assert_eq!(
+            Err(SocketAddrError::InvalidZone { zone: "eth0".to_owned() }),
+            SocketAddr::try_from(&MultiAddr::from(Segment::Ip6zone("eth0".to_owned()))));
+        assert_eq!(
+            Err(SocketAddrError::TrailingSegments { rest: Segment::Ws.into() }),
+            SocketAddr::try_from(&(ip4 + Segment::Tcp(80) + Segment::Ws)));
+    }
+}
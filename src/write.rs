@@ -2,7 +2,7 @@ use std::io;
 use std::net::{ Ipv4Addr, Ipv6Addr };
 
 use varmint::WriteVarInt;
-use mhash::WriteMultiHash;
+use mhash::{ MultiHash, WriteMultiHash };
 
 use { MultiAddr, Segment };
 use Segment::*;
@@ -10,8 +10,12 @@ use Segment::*;
 trait WriteHelper {
     fn write_u8(&mut self, val: u8) -> io::Result<()>;
     fn write_u16_be(&mut self, val: u16) -> io::Result<()>;
+    fn write_u64_be(&mut self, val: u64) -> io::Result<()>;
     fn write_ipv4addr(&mut self, addr: &Ipv4Addr) -> io::Result<()>;
     fn write_ipv6addr(&mut self, addr: &Ipv6Addr) -> io::Result<()>;
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()>;
+    fn write_string(&mut self, string: &str) -> io::Result<()>;
+    fn write_prefixed_multihash(&mut self, multihash: &MultiHash) -> io::Result<()>;
     fn write_segment(&mut self, segment: &Segment) -> io::Result<()>;
 }
 
@@ -26,6 +30,11 @@ impl<W: io::Write> WriteHelper for W {
         Ok(())
     }
 
+    fn write_u64_be(&mut self, val: u64) -> io::Result<()> {
+        try!(self.write_all(&val.to_be_bytes()));
+        Ok(())
+    }
+
     fn write_ipv4addr(&mut self, addr: &Ipv4Addr) -> io::Result<()> {
         try!(self.write_all(&addr.octets()));
         Ok(())
@@ -36,6 +45,22 @@ impl<W: io::Write> WriteHelper for W {
         Ok(())
     }
 
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        try!(self.write_usize_varint(bytes.len()));
+        try!(self.write_all(bytes));
+        Ok(())
+    }
+
+    fn write_string(&mut self, string: &str) -> io::Result<()> {
+        self.write_bytes(string.as_bytes())
+    }
+
+    fn write_prefixed_multihash(&mut self, multihash: &MultiHash) -> io::Result<()> {
+        try!(self.write_usize_varint(multihash.output_len()));
+        try!(self.write_multihash(multihash));
+        Ok(())
+    }
+
     fn write_segment(&mut self, segment: &Segment) -> io::Result<()> {
         try!(self.write_u64_varint(segment.code()));
         match *segment {
@@ -43,19 +68,116 @@ impl<W: io::Write> WriteHelper for W {
                 try!(self.write_ipv4addr(addr)),
             IP6(ref addr) =>
                 try!(self.write_ipv6addr(addr)),
+            Memory(id) =>
+                try!(self.write_u64_be(id)),
+            Ip6zone(ref zone) =>
+                try!(self.write_string(zone)),
+            Ipcidr(mask) =>
+                try!(self.write_u8(mask)),
             Udp(port) | Dccp(port) | Sctp(port) | Tcp(port) =>
                 try!(self.write_u16_be(port)),
-            Ipfs(ref multihash) => {
-                try!(self.write_usize_varint(multihash.output_len()));
-                try!(self.write_multihash(multihash));
+            Ipfs(ref multihash) | Certhash(ref multihash) =>
+                try!(self.write_prefixed_multihash(multihash)),
+            Dns(ref name) | Dns4(ref name) | Dns6(ref name) | Dnsaddr(ref name) =>
+                try!(self.write_string(name)),
+            Sni(ref name) =>
+                try!(self.write_string(name)),
+            Onion(ref hash, port) => {
+                try!(self.write_all(hash));
+                try!(self.write_u16_be(port));
+            }
+            Onion3(ref key, port) => {
+                try!(self.write_all(key));
+                try!(self.write_u16_be(port));
             }
-            Udt | Utp | Http | Https => {
+            Garlic64(ref bytes) | Garlic32(ref bytes) =>
+                try!(self.write_bytes(bytes)),
+            Unix(ref path) | HttpPath(ref path) =>
+                try!(self.write_string(path)),
+            Unknown(_, ref data) =>
+                try!(self.write_all(data)),
+            Udt | Utp | Http | Https | Quic | QuicV1 | WebTransport | Ws | Wss
+                | Tls | Noise | Plaintextv2 | P2pCircuit | WebRTC | WebRTCDirect
+                | P2pStardust | P2pWebRTCDirect | P2pWebRTCStar | P2pWebSocketStar => {
             }
         }
         Ok(())
     }
 }
 
+/// Write a single segment in its binary form.
+pub(crate) fn write_single_segment<W: io::Write>(writer: &mut W, segment: &Segment) -> io::Result<()> {
+    writer.write_segment(segment)
+}
+
+/// The length of the varint encoding of `value`.
+fn varint_len(value: u64) -> usize {
+    let mut len = 1;
+    let mut value = value >> 7;
+    while value != 0 {
+        len += 1;
+        value >>= 7;
+    }
+    len
+}
+
+/// The length of `len` bytes with a varint length prefix.
+fn prefixed_len(len: usize) -> usize {
+    varint_len(len as u64) + len
+}
+
+impl Segment {
+    /// Get the length of the binary encoding of this segment, without
+    /// encoding it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::Segment;
+    ///
+    /// assert_eq!(3, Segment::Tcp(22).encoded_len());
+    /// assert_eq!(13, Segment::Dns("example.com".to_owned()).encoded_len());
+    /// ```
+    pub fn encoded_len(&self) -> usize {
+        varint_len(self.code()) + match *self {
+            IP4(_) => 4,
+            IP6(_) => 16,
+            Memory(_) => 8,
+            Ipcidr(_) => 1,
+            Udp(_) | Dccp(_) | Sctp(_) | Tcp(_) => 2,
+            Ipfs(ref multihash) | Certhash(ref multihash) =>
+                prefixed_len(multihash.output_len()),
+            Ip6zone(ref name) | Dns(ref name) | Dns4(ref name) | Dns6(ref name)
+                | Dnsaddr(ref name) | Sni(ref name) | Unix(ref name) | HttpPath(ref name) =>
+                prefixed_len(name.len()),
+            Onion(ref hash, _) => hash.len() + 2,
+            Onion3(ref key, _) => key.len() + 2,
+            Garlic64(ref bytes) | Garlic32(ref bytes) => prefixed_len(bytes.len()),
+            Unknown(_, ref data) => data.len(),
+            Udt | Utp | Http | Https | Quic | QuicV1 | WebTransport | Ws | Wss
+                | Tls | Noise | Plaintextv2 | P2pCircuit | WebRTC | WebRTCDirect
+                | P2pStardust | P2pWebRTCDirect | P2pWebRTCStar | P2pWebSocketStar => 0,
+        }
+    }
+}
+
+impl MultiAddr {
+    /// Get the length of the binary encoding of this `MultiAddr`, without
+    /// encoding it, e.g. to reserve space in a buffer.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::MultiAddr;
+    ///
+    /// let addr: MultiAddr = "/ip4/1.2.3.4/tcp/22".parse().unwrap();
+    /// assert_eq!(8, addr.encoded_len());
+    /// ```
+    pub fn encoded_len(&self) -> usize {
+        self.segments().iter().map(Segment::encoded_len).sum()
+    }
+}
+
 /// A trait to allow writing a `MultiAddr` to an object.
 ///
 /// This is primarily intended to provide support for the `io::Write` trait,
@@ -83,6 +205,24 @@ pub trait WriteMultiAddr {
     /// assert_eq!(vec![4, 1, 2, 3, 4], buffer);
     /// ```
     fn write_multiaddr(&mut self, multiaddr: &MultiAddr) -> io::Result<()>;
+
+    /// Write a `MultiAddr` prefixed with its encoded length as a varint, see
+    /// `ReadMultiAddr::read_multiaddr_length_prefixed`.
+    ///
+    /// # Errors
+    ///
+    /// As for `write_multiaddr`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use maddr::{ MultiAddr, Segment, WriteMultiAddr };
+    ///
+    /// let mut buffer = vec![];
+    /// buffer.write_multiaddr_length_prefixed(&Segment::Tcp(22).into()).unwrap();
+    /// assert_eq!(vec![3, 6, 0, 22], buffer);
+    /// ```
+    fn write_multiaddr_length_prefixed(&mut self, multiaddr: &MultiAddr) -> io::Result<()>;
 }
 
 impl<W: io::Write> WriteMultiAddr for W {
@@ -92,6 +232,11 @@ impl<W: io::Write> WriteMultiAddr for W {
         }
         Ok(())
     }
+
+    fn write_multiaddr_length_prefixed(&mut self, multiaddr: &MultiAddr) -> io::Result<()> {
+        try!(self.write_usize_varint(multiaddr.encoded_len()));
+        self.write_multiaddr(multiaddr)
+    }
 }
 
 #[cfg(test)]
@@ -121,6 +266,41 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn ip6zone() {
+        let mut buffer = Vec::with_capacity(23);
+        buffer.write_multiaddr(&MultiAddr::new(vec![
+            Segment::Ip6zone("eth0".to_owned()),
+            Segment::IP6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+        ])).unwrap();
+        assert_eq!(buffer, vec![
+            42, 4, b'e', b't', b'h', b'0',
+            41,
+            0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        ]);
+    }
+
+    #[test]
+    fn ipcidr() {
+        let mut buffer = Vec::with_capacity(7);
+        buffer.write_multiaddr(&MultiAddr::new(vec![
+            Segment::IP4(Ipv4Addr::new(10, 0, 0, 0)),
+            Segment::Ipcidr(8),
+        ])).unwrap();
+        assert_eq!(buffer, vec![4, 10, 0, 0, 0, 43, 8]);
+    }
+
+    #[test]
+    fn memory() {
+        let mut buffer = Vec::with_capacity(10);
+        buffer.write_multiaddr(&MultiAddr::new(vec![Segment::Memory(1234)])).unwrap();
+        assert_eq!(buffer, vec![
+            0b10001001, 0b00000110,
+            0, 0, 0, 0, 0, 0, 0x04, 0xd2,
+        ]);
+    }
+
     #[test]
     fn ipfs() {
         let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
@@ -142,4 +322,155 @@ mod tests {
             194, 13, 183, 106, 104, 145, 28, 11
         ]);
     }
+
+    #[test]
+    fn webtransport() {
+        let multihash = MultiHash::new(MultiHashVariant::Sha2_256, &[
+            213, 46, 187, 137, 216, 91, 2, 162,
+            132, 148, 130, 3, 166, 47, 242, 131,
+            137, 197, 124, 159, 66, 190, 236, 78,
+            194, 13, 183, 106, 104, 145, 28, 11,
+        ]).unwrap();
+
+        let mut buffer = Vec::with_capacity(41);
+        buffer.write_multiaddr(&MultiAddr::new(vec![
+            Segment::QuicV1,
+            Segment::WebTransport,
+            Segment::Certhash(multihash),
+        ])).unwrap();
+        assert_eq!(buffer, vec![
+            0b11001101, 0b00000011,
+            0b11010001, 0b00000011,
+            0b11010010, 0b00000011, 34,
+            0x12, 32,
+            213, 46, 187, 137, 216, 91, 2, 162,
+            132, 148, 130, 3, 166, 47, 242, 131,
+            137, 197, 124, 159, 66, 190, 236, 78,
+            194, 13, 183, 106, 104, 145, 28, 11
+        ]);
+    }
+
+    #[test]
+    fn dns4() {
+        let mut buffer = Vec::with_capacity(16);
+        buffer.write_multiaddr(&MultiAddr::new(vec![
+            Segment::Dns4("example.com".to_owned()),
+            Segment::Tcp(4001),
+        ])).unwrap();
+        assert_eq!(buffer, vec![
+            54, 11,
+            b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+            6, 0x0f, 0xa1,
+        ]);
+    }
+
+    #[test]
+    fn tls_sni_ws() {
+        let mut buffer = Vec::with_capacity(27);
+        buffer.write_multiaddr(&MultiAddr::new(vec![
+            Segment::IP4(Ipv4Addr::new(1, 2, 3, 4)),
+            Segment::Tcp(443),
+            Segment::Tls,
+            Segment::Sni("example.com".to_owned()),
+            Segment::Ws,
+        ])).unwrap();
+        assert_eq!(buffer, vec![
+            4, 1, 2, 3, 4,
+            6, 0x01, 0xbb,
+            0b11000000, 0b00000011,
+            0b11000001, 0b00000011, 11,
+            b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+            0b11011101, 0b00000011,
+        ]);
+    }
+
+    #[test]
+    fn wss_noise_plaintextv2() {
+        let mut buffer = Vec::with_capacity(8);
+        buffer.write_multiaddr(&MultiAddr::new(vec![
+            Segment::Wss,
+            Segment::Noise,
+            Segment::Plaintextv2,
+        ])).unwrap();
+        assert_eq!(buffer, vec![
+            0b11011110, 0b00000011,
+            0b11000110, 0b00000011,
+            0b11100001, 0b11011000, 0b11000001, 0b00000011,
+        ]);
+    }
+
+    #[test]
+    fn circuit_webrtc() {
+        let mut buffer = Vec::with_capacity(6);
+        buffer.write_multiaddr(&MultiAddr::new(vec![
+            Segment::WebRTCDirect,
+            Segment::P2pCircuit,
+            Segment::WebRTC,
+        ])).unwrap();
+        assert_eq!(buffer, vec![
+            0b10011000, 0b00000010,
+            0b10100010, 0b00000010,
+            0b10011001, 0b00000010,
+        ]);
+    }
+
+    #[test]
+    fn onion() {
+        let mut buffer = Vec::with_capacity(14);
+        buffer.write_multiaddr(&MultiAddr::new(vec![Segment::Onion(
+            [154, 24, 8, 115, 6, 54, 144, 67, 9, 31],
+            1234)])).unwrap();
+        assert_eq!(buffer, vec![
+            0b10111100, 0b00000011,
+            154, 24, 8, 115, 6, 54, 144, 67, 9, 31,
+            0x04, 0xd2,
+        ]);
+    }
+
+    #[test]
+    fn garlic32() {
+        let mut buffer = Vec::with_capacity(35);
+        buffer.write_multiaddr(&MultiAddr::new(vec![Segment::Garlic32(vec![
+            239, 188, 212, 93, 12, 93, 199, 151, 129, 172, 111, 32, 234, 80, 85, 160,
+            54, 175, 180, 141, 69, 165, 46, 125, 104, 236, 125, 67, 56, 145, 158, 105,
+        ])])).unwrap();
+        assert_eq!(buffer, vec![
+            0b10111111, 0b00000011, 32,
+            239, 188, 212, 93, 12, 93, 199, 151, 129, 172, 111, 32, 234, 80, 85, 160,
+            54, 175, 180, 141, 69, 165, 46, 125, 104, 236, 125, 67, 56, 145, 158, 105,
+        ]);
+    }
+
+    #[test]
+    fn unknown() {
+        let mut buffer = Vec::with_capacity(9);
+        buffer.write_multiaddr(&MultiAddr::new(vec![
+            Segment::Tcp(4001),
+            Segment::Unknown(999, vec![0xde, 0xad, 0xbe, 0xef]),
+        ])).unwrap();
+        assert_eq!(buffer, vec![
+            6, 0x0f, 0xa1,
+            0b11100111, 0b00000111, 0xde, 0xad, 0xbe, 0xef,
+        ]);
+    }
+
+    #[test]
+    fn unix() {
+        let mut buffer = Vec::with_capacity(8);
+        buffer.write_multiaddr(&MultiAddr::new(vec![
+            Segment::Unix("/tmp/".to_owned()),
+        ])).unwrap();
+        assert_eq!(buffer, vec![
+            0b10010000, 0b00000011, 5,
+            b'/', b't', b'm', b'p', b'/',
+        ]);
+    }
+
+    #[test]
+    fn length_prefixed() {
+        let mut buffer = Vec::with_capacity(10);
+        buffer.write_multiaddr_length_prefixed(&MultiAddr::new(vec![Segment::Tcp(4001)])).unwrap();
+        buffer.write_multiaddr_length_prefixed(&MultiAddr::new(vec![])).unwrap();
+        assert_eq!(buffer, vec![3, 6, 0x0f, 0xa1, 0]);
+    }
 }
@@ -0,0 +1,52 @@
+//! Checks that borrowed multiaddrs can be validated, iterated and displayed
+//! without allocating. This needs a counting global allocator, so lives in its
+//! own test binary rather than affecting the library's unit tests.
+
+extern crate maddr;
+
+use std::alloc::{ GlobalAlloc, Layout, System };
+use std::cell::Cell;
+use std::io::Write;
+
+use maddr::{ MultiAddr, MultiAddrRef, WriteMultiAddr };
+
+/// Counts allocations made by the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn multiaddr_ref() {
+    let addr: MultiAddr = concat!(
+        "/ip4/1.2.3.4/tcp/22/dns/example.com",
+        "/garlic32/566niximlxdzpanmn4qouucvua3k7neniwss47li5r6ugoertzuq").parse().unwrap();
+    let mut bytes = Vec::new();
+    bytes.write_multiaddr(&addr).unwrap();
+    let mut buffer = [0; 128];
+    let before = allocations();
+    let addr = MultiAddrRef::new(&bytes).unwrap();
+    assert!(addr.segments().any(|segment| segment.name() == "tcp"));
+    write!(&mut buffer[..], "{}", addr).unwrap();
+    assert_eq!(0, allocations() - before);
+    assert!(buffer.starts_with(b"/ip4/1.2.3.4/tcp/22/dns/example.com/garlic32/566niximlx"));
+}